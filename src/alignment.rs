@@ -0,0 +1,193 @@
+use serde::{Deserialize, Serialize};
+use crate::Genome;
+
+// how two genomes are compared when a cell reads a message
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub enum Alignment {
+    // count mismatched sites, each base of a length difference counts as a mismatch
+    #[default]
+    Hamming,
+    // full Levenshtein edit distance
+    Levenshtein,
+    // Needleman-Wunsch with unit costs restricted to a band around the diagonal,
+    // widened to the length difference if that is larger
+    Banded { band: usize }
+}
+
+const INF: u32 = u32::MAX / 2;
+// band used to map a genome back to the reference when the alignment has none of its own,
+// so the traceback matrix grows with the genome's length rather than its square
+const ALIGN_BAND: usize = 64;
+
+// edit distance restricted to |i - j| <= band, keeping only the previous row of the matrix.
+// row i holds columns i - band ..= i + band
+fn edit_distance(a: &[u8], b: &[u8], band: usize) -> u32 {
+    let band = band.max(a.len().abs_diff(b.len())).min(a.len().max(b.len()));
+    let width = 2 * band + 1;
+    let column = |i: usize, k: usize| (i + k).checked_sub(band).filter(|j| *j <= b.len());
+    let mut previous: Vec<u32> = (0..width).map(|k| column(0, k).map_or(INF, |j| j as u32)).collect();
+    let mut current = vec![INF; width];
+    for i in 1..=a.len() {
+        for k in 0..width {
+            current[k] = match column(i, k) {
+                None => INF,
+                Some(0) => i as u32,
+                Some(j) => {
+                    let substitution = previous[k] + (a[i - 1] != b[j - 1]) as u32;
+                    let deletion = previous.get(k + 1).copied().unwrap_or(INF) + 1;
+                    let insertion = if k > 0 { current[k - 1] } else { INF } + 1;
+                    substitution.min(deletion).min(insertion)
+                }
+            };
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    previous[b.len() + band - a.len()]
+}
+
+// edit distance matrix restricted to |i - j| <= band, row i is a prefix of a, column j of b
+struct EditMatrix {
+    cells: Vec<u32>,
+    band: usize,
+    width: usize
+}
+
+impl EditMatrix {
+    fn new(a: &[u8], b: &[u8], band: usize) -> EditMatrix {
+        let band = band.max(a.len().abs_diff(b.len()));
+        let width = 2 * band + 1;
+        let mut matrix = EditMatrix { cells: vec![INF; (a.len() + 1) * width], band, width };
+        for i in 0..=a.len() {
+            for j in i.saturating_sub(band)..=(i + band).min(b.len()) {
+                let cost = if i == 0 {
+                    j as u32
+                } else if j == 0 {
+                    i as u32
+                } else {
+                    let substitution = matrix.get(i - 1, j - 1) + (a[i - 1] != b[j - 1]) as u32;
+                    let deletion = matrix.get(i - 1, j) + 1;
+                    let insertion = matrix.get(i, j - 1) + 1;
+                    substitution.min(deletion).min(insertion)
+                };
+                matrix.set(i, j, cost);
+            }
+        }
+        matrix
+    }
+
+    fn index(&self, i: usize, j: usize) -> Option<usize> {
+        if i.abs_diff(j) > self.band { return None }
+        Some(i * self.width + j + self.band - i)
+    }
+
+    fn get(&self, i: usize, j: usize) -> u32 {
+        self.index(i, j).map_or(INF, |k| self.cells[k])
+    }
+
+    fn set(&mut self, i: usize, j: usize, cost: u32) {
+        let k = self.index(i, j).unwrap();
+        self.cells[k] = cost;
+    }
+}
+
+impl Alignment {
+    fn band(&self, a: &[u8], b: &[u8]) -> usize {
+        match self {
+            Alignment::Banded { band } => *band,
+            _ => a.len().max(b.len())
+        }
+    }
+
+    // band for align, which has to keep the whole matrix to trace back through
+    fn align_band(&self) -> usize {
+        match self {
+            Alignment::Banded { band } => *band,
+            _ => ALIGN_BAND
+        }
+    }
+
+    // number of differences a cell perceives between its own genome and a message
+    pub fn difference(&self, g1: &Genome, g2: &Genome) -> f32 {
+        if g1 == g2 {
+            return 0.0
+        }
+        match self {
+            Alignment::Hamming => g1.hamming(g2) as f32,
+            _ => {
+                let (a, b): (Vec<u8>, Vec<u8>) = (g1.codes().collect(), g2.codes().collect());
+                edit_distance(&a, &b, self.band(&a, &b)) as f32
+            }
+        }
+    }
+
+    // map every site of the reference to the position of genome aligned against it, or None
+    // where the genome has lost it. Hamming and Levenshtein align within ALIGN_BAND of the diagonal
+    // here, widened to the length difference if that is larger.
+    pub fn align(&self, reference: &Genome, genome: &Genome) -> Vec<Option<usize>> {
+        let (a, b): (Vec<u8>, Vec<u8>) = (reference.codes().collect(), genome.codes().collect());
+        let matrix = EditMatrix::new(&a, &b, self.align_band());
+        let mut mapped = vec![None; a.len()];
+        let (mut i, mut j) = (a.len(), b.len());
+        // trace back preferring matches, then deletions, then insertions
+        while i > 0 && j > 0 {
+            let cost = matrix.get(i, j);
+            if cost == matrix.get(i - 1, j - 1) + (a[i - 1] != b[j - 1]) as u32 {
+                mapped[i - 1] = Some(j - 1);
+                i -= 1;
+                j -= 1;
+            } else if cost == matrix.get(i - 1, j) + 1 {
+                i -= 1;
+            } else {
+                j -= 1;
+            }
+        }
+        mapped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::prelude::*;
+    use rand_chacha::ChaCha8Rng;
+    use super::*;
+
+    fn codes(s: &str) -> Vec<u8> {
+        s.parse::<Genome>().unwrap().codes().collect()
+    }
+
+    #[test]
+    fn band_is_widened_to_the_length_difference() {
+        let (a, b) = (codes("GATTACA"), codes("GATTACAGATTACA"));
+        assert_eq!(EditMatrix::new(&a, &b, 0).get(a.len(), b.len()), 7);
+        assert_eq!(edit_distance(&a, &b, 0), 7);
+    }
+
+    #[test]
+    fn narrow_band_misses_far_alignments() {
+        // shifting by three needs three insertions and three deletions, outside a band of 2
+        let (a, b) = (codes("TTTGATTACA"), codes("GATTACATTT"));
+        assert_eq!(edit_distance(&a, &b, 3), 6);
+        assert!(edit_distance(&a, &b, 2) > 6);
+        assert_eq!(EditMatrix::new(&a, &b, 2).get(0, 3), INF);
+    }
+
+    #[test]
+    fn two_rows_match_the_full_matrix() {
+        let mut rng = ChaCha8Rng::seed_from_u64(7);
+        for _ in 0..2000 {
+            let a: Vec<u8> = (0..rng.gen_range(0..20)).map(|_| rng.gen_range(0..4)).collect();
+            let b: Vec<u8> = (0..rng.gen_range(0..20)).map(|_| rng.gen_range(0..4)).collect();
+            let band = rng.gen_range(0..24);
+            assert_eq!(edit_distance(&a, &b, band), EditMatrix::new(&a, &b, band).get(a.len(), b.len()));
+        }
+    }
+
+    #[test]
+    fn align_maps_sites_past_a_deletion() {
+        let reference = "GATTACA".parse().unwrap();
+        let genome = "GATACA".parse().unwrap();
+        let mapped = Alignment::Levenshtein.align(&reference, &genome);
+        assert_eq!(mapped.iter().filter(|p| p.is_none()).count(), 1);
+        assert_eq!(mapped[6], Some(5));
+    }
+}
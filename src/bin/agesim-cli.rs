@@ -0,0 +1,168 @@
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+use clap::{Args, Parser, Subcommand};
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use agesim::{Genome, Mask, Settings, Organism};
+
+// headless front end to the model in lib.rs, for running on servers and in scripts
+#[derive(Parser)]
+#[command(name = "agesim-cli", about = "Run agesim simulations without a browser")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Grow an organism for a number of steps and write per-step statistics as CSV
+    Run(RunArgs)
+}
+
+#[derive(Args)]
+struct RunArgs {
+    /// Number of growth steps to simulate
+    #[arg(long, default_value_t = 1000)]
+    steps: u32,
+    /// JSON file of Settings; missing fields take their defaults and flags below override it
+    #[arg(long)]
+    settings: Option<PathBuf>,
+    /// Continue from a snapshot instead of starting a new organism
+    #[arg(long, conflicts_with_all = ["settings", "length", "genome", "genome_length", "mutation_rate", "growth_rate", "seed", "mask"])]
+    resume: Option<PathBuf>,
+    /// Save a snapshot here when the run finishes (.json for json, anything else for binary)
+    #[arg(long)]
+    checkpoint: Option<PathBuf>,
+    /// Also overwrite the checkpoint every this many steps
+    #[arg(long, requires = "checkpoint", value_parser = clap::value_parser!(u32).range(1..))]
+    checkpoint_every: Option<u32>,
+    /// Write every cell's division history here when the run finishes (.xml or .nexml for NeXML, anything else for Newick)
+    #[arg(long)]
+    lineage: Option<PathBuf>,
+    /// Side length of the square grid
+    #[arg(long)]
+    length: Option<usize>,
+    /// Reference genome every cell starts from
+    #[arg(long)]
+    genome: Option<String>,
+    /// Start from a random reference genome of this many bases, drawn from the seed
+    #[arg(long, conflicts_with = "genome")]
+    genome_length: Option<usize>,
+    /// Per-step probability of mutation
+    #[arg(long)]
+    mutation_rate: Option<f32>,
+    /// Probability of mutation on division
+    #[arg(long)]
+    growth_rate: Option<f32>,
+    /// Seed for the simulation RNG
+    #[arg(long)]
+    seed: Option<u32>,
+    /// Tissue mask to grow within, as text ('.' outside, '#' tissue, '@' seed) or, with the png feature, a .png
+    #[arg(long)]
+    mask: Option<PathBuf>,
+    /// Also write the entropy of every reference site as a column of its own
+    #[arg(long)]
+    site_entropy: bool,
+    /// Write the CSV here instead of stdout
+    #[arg(short, long)]
+    output: Option<PathBuf>
+}
+
+impl RunArgs {
+    fn settings(&self) -> Result<Settings, Box<dyn Error>> {
+        let base: Settings = match &self.settings {
+            Some(path) => serde_json::from_str(&fs::read_to_string(path)?)?,
+            None => Settings::default()
+        };
+        let seed = self.seed.unwrap_or(base.seed);
+        let genome: Genome = match (&self.genome, self.genome_length) {
+            (Some(genome), _) => genome.parse()?,
+            (None, Some(len)) => Genome::random(len, &mut ChaCha8Rng::seed_from_u64(seed as u64)),
+            (None, None) => base.genome.parse()?
+        };
+        let mask = match &self.mask {
+            Some(path) => Some(Mask::load(path)?),
+            None => base.mask
+        };
+        // the arena has to be large enough to hold the whole mask
+        let length = self.length.unwrap_or(base.length).max(
+            mask.as_ref().map_or(0, |mask| mask.width().max(mask.height()))
+        );
+        Ok(Settings {
+            length,
+            mask,
+            genome: genome.to_string(),
+            mutation_rate: self.mutation_rate.unwrap_or(base.mutation_rate),
+            growth_rate: self.growth_rate.unwrap_or(base.growth_rate),
+            seed,
+            ..base
+        })
+    }
+
+    fn organism(&self) -> Result<Organism, Box<dyn Error>> {
+        match &self.resume {
+            Some(path) => Ok(Organism::load(path)?),
+            None => Ok(self.settings()?.init_organism()?)
+        }
+    }
+}
+
+fn write_header(out: &mut impl Write, settings: &Settings, site_entropy: bool) -> io::Result<()> {
+    write!(out, "step,mean_age,mean_cell_age,size,samplesize,mutations,senescent,deaths,haplotypes,total_entropy")?;
+    if site_entropy {
+        for (i, bp) in settings.genome.chars().enumerate() {
+            write!(out, ",entropy_{}_{}", i, bp)?;
+        }
+    }
+    writeln!(out)
+}
+
+fn write_row(out: &mut impl Write, org: &Organism, site_entropy: bool) -> io::Result<()> {
+    write!(
+        out, "{},{},{},{},{},{},{},{},{},{}",
+        org.step, org.mean_age(), org.mean_cell_age(), org.size, org.samplesize, org.mutations, org.senescent_count(), org.deaths,
+        org.haplotypes.len(), org.total_entropy()
+    )?;
+    if site_entropy {
+        for (_, entropy) in org.entropy() {
+            write!(out, ",{}", entropy)?;
+        }
+    }
+    writeln!(out)
+}
+
+fn run(args: RunArgs) -> Result<(), Box<dyn Error>> {
+    let mut organism = args.organism()?;
+    let mut out: BufWriter<Box<dyn Write>> = match &args.output {
+        Some(path) => BufWriter::new(Box::new(File::create(path)?)),
+        None => BufWriter::new(Box::new(io::stdout().lock()))
+    };
+    write_header(&mut out, &organism.settings, args.site_entropy)?;
+    write_row(&mut out, &organism, args.site_entropy)?;
+    for step in 1..=args.steps {
+        organism = organism.growstep();
+        write_row(&mut out, &organism, args.site_entropy)?;
+        if let (Some(path), Some(every)) = (&args.checkpoint, args.checkpoint_every) {
+            if step % every == 0 {
+                organism.save(path)?;
+            }
+        }
+    }
+    if let Some(path) = &args.checkpoint {
+        organism.save(path)?;
+    }
+    if let Some(path) = &args.lineage {
+        let nexml = path.extension().is_some_and(|e| e == "xml" || e == "nexml");
+        fs::write(path, if nexml { organism.to_nexml() } else { organism.to_newick() })?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    match Cli::parse().command {
+        Command::Run(args) => run(args)
+    }
+}
@@ -0,0 +1,86 @@
+use serde::{Deserialize, Serialize};
+use crate::Location;
+
+// what lies past the edges of the length x length arena
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Boundary {
+    // sites past the edge don't exist, so border cells have fewer neighbours
+    #[default]
+    HardWall,
+    // the arena wraps around into a torus
+    Periodic,
+    // sites past the edge mirror those inside it, so border cells see a full neighbourhood
+    Reflecting,
+    // no arena at all, the organism starts at the origin and grows without limit
+    Open
+}
+
+// fold a coordinate back into 0..length, mirroring about the first and last sites
+fn reflect(x: i32, length: i32) -> i32 {
+    if length <= 1 {
+        return 0
+    }
+    let period = 2 * (length - 1);
+    let r = x.rem_euclid(period);
+    if r < length { r } else { period - r }
+}
+
+impl Boundary {
+    // where a coordinate along an axis of length sites ends up, None past a hard wall
+    pub fn axis(&self, v: i32, length: usize) -> Option<i32> {
+        let length = length as i32;
+        match self {
+            Boundary::HardWall => if (v >= 0) & (v < length) { Some(v) } else { None },
+            Boundary::Periodic => Some(v.rem_euclid(length)),
+            Boundary::Reflecting => Some(reflect(v, length)),
+            Boundary::Open => Some(v)
+        }
+    }
+
+    // the site a location refers to in an arena of side length and depth layers,
+    // None if it lies past a hard wall
+    pub fn resolve(&self, location: &Location, length: usize, depth: usize) -> Option<Location> {
+        Some(Location {
+            x: self.axis(location.x, length)?,
+            y: self.axis(location.y, length)?,
+            z: self.axis(location.z, depth)?
+        })
+    }
+
+    // offset from i to j, taking the shorter way around a periodic arena
+    pub fn displacement(&self, j: &Location, i: &Location, length: usize, depth: usize) -> (i32, i32, i32) {
+        let (dx, dy, dz) = (j.x - i.x, j.y - i.y, j.z - i.z);
+        match self {
+            Boundary::Periodic => {
+                let wrap = |d: i32, length: usize| {
+                    let length = length as i32;
+                    let d = d.rem_euclid(length);
+                    if d > length / 2 { d - length } else { d }
+                };
+                (wrap(dx, length), wrap(dy, length), wrap(dz, depth))
+            },
+            _ => (dx, dy, dz)
+        }
+    }
+
+    // whether an offset along an axis of length sites reaches a site no other offset within
+    // reach does. around a torus of even length +length/2 lands on the same site as -length/2
+    pub fn distinct(&self, offset: i32, length: usize) -> bool {
+        match self {
+            Boundary::Periodic => offset <= (length as i32 - 1) / 2,
+            _ => true
+        }
+    }
+
+    // how far along either axis a message can be heard from. around a torus anything beyond
+    // half the arena would reach the same site twice, elsewhere messages travel at most the
+    // arena's length. an open arena has no length to stop them, so they go up to max_reach
+    pub fn reach(&self, radius: f32, length: usize, max_reach: usize) -> i32 {
+        let limit = match self {
+            Boundary::Periodic => (length / 2) as f32,
+            Boundary::Open => max_reach as f32,
+            _ => length as f32
+        };
+        radius.min(limit) as i32
+    }
+}
@@ -0,0 +1,27 @@
+use rand::prelude::*;
+use serde::{Deserialize, Serialize};
+
+// per-step probability that a cell dies and frees its site for a neighbour to divide into,
+// base_rate + age_rate * age, plus senescent_rate for senescent cells
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Death {
+    pub base_rate: f32,
+    pub age_rate: f32,
+    pub senescent_rate: f32
+}
+
+impl Death {
+    pub fn enabled(&self) -> bool {
+        (self.base_rate > 0.0) || (self.age_rate > 0.0) || (self.senescent_rate > 0.0)
+    }
+
+    pub fn probability(&self, age: f32, senescent: bool) -> f32 {
+        let rate = self.base_rate + self.age_rate * age + if senescent { self.senescent_rate } else { 0.0 };
+        rate.clamp(0.0, 1.0)
+    }
+
+    pub fn triggers<R: Rng + ?Sized>(&self, age: f32, senescent: bool, rng: &mut R) -> bool {
+        rng.gen::<f32>() < self.probability(age, senescent)
+    }
+}
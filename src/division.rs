@@ -0,0 +1,47 @@
+use serde::{Deserialize, Serialize};
+
+// how likely a cell is to divide this step given its age readout. free and
+// adjacent count the empty and all sites next to it inside the grid.
+pub trait DivisionPolicy {
+    fn probability(&self, age: f32, free: usize, adjacent: usize) -> f32;
+}
+
+// the built-in policies, selectable in Settings
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum DivisionPolicyKind {
+    // rate * exp(-decay * age)
+    Exponential { rate: f32, decay: f32 },
+    // Hill repression, rate / (1 + (age / half_age)^n)
+    Hill { rate: f32, half_age: f32, n: f32 },
+    // rate until the age readout reaches threshold, then no division
+    Threshold { rate: f32, threshold: f32 },
+    // rate / (1 + exp(steepness * (age - midpoint)))
+    Logistic { rate: f32, midpoint: f32, steepness: f32 },
+    // Exponential scaled by the fraction of adjacent sites still free
+    ContactInhibition { rate: f32, decay: f32 }
+}
+
+impl Default for DivisionPolicyKind {
+    fn default() -> Self {
+        DivisionPolicyKind::Exponential { rate: 0.02, decay: 1.0 }
+    }
+}
+
+impl DivisionPolicy for DivisionPolicyKind {
+    fn probability(&self, age: f32, free: usize, adjacent: usize) -> f32 {
+        match self {
+            DivisionPolicyKind::Exponential { rate, decay } => rate * (-decay * age).exp(),
+            DivisionPolicyKind::Hill { rate, half_age, n } => rate / (1.0 + (age / half_age).powf(*n)),
+            DivisionPolicyKind::Threshold { rate, threshold } => if age < *threshold { *rate } else { 0.0 },
+            DivisionPolicyKind::Logistic { rate, midpoint, steepness } => {
+                rate / (1.0 + (steepness * (age - midpoint)).exp())
+            },
+            DivisionPolicyKind::ContactInhibition { rate, decay } => {
+                if adjacent == 0 {
+                    return 0.0
+                }
+                rate * (-decay * age).exp() * free as f32 / adjacent as f32
+            }
+        }
+    }
+}
@@ -0,0 +1,91 @@
+use std::collections::HashSet;
+use serde::{Deserialize, Serialize};
+use crate::Genome;
+
+// a genome a cell heard from another cell during growstep
+pub struct Message<'a> {
+    pub genome: &'a Genome,
+    pub distance: f32,
+    // differences between the message and the receiving cell's own genome
+    pub difference: f32
+}
+
+// turns the messages a cell received this step into its estimate of organismal age
+pub trait AgeEstimator {
+    fn estimate(&self, genome: &Genome, messages: &[Message], previous: f32) -> f32;
+}
+
+// the built-in estimators, selectable in Settings
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub enum AgeEstimatorKind {
+    // sum of differences over every message, grows with the number of cells heard
+    #[default]
+    RawSum,
+    // differences per message received
+    MeanPerMessage,
+    // mean differences with each message weighted by 1 / (1 + distance)
+    DistanceWeighted,
+    // mean Jaccard distance between the k-mer sets of the cell and each message, k at most 32
+    KmerJaccard { k: usize },
+    // MeanPerMessage smoothed over steps, alpha is the weight of the newest reading
+    MovingAverage { alpha: f32 }
+}
+
+fn mean_difference(messages: &[Message]) -> f32 {
+    if messages.is_empty() {
+        return 0.0
+    }
+    messages.iter().map(|m| m.difference).sum::<f32>() / messages.len() as f32
+}
+
+// every k-mer of the genome packed 2 bits per base
+fn kmers(genome: &Genome, k: usize) -> HashSet<u64> {
+    let k = k.clamp(1, 32);
+    let mask = if k == 32 { u64::MAX } else { (1u64 << (2 * k)) - 1 };
+    let mut window = 0u64;
+    let mut kmers = HashSet::new();
+    for (i, code) in genome.codes().enumerate() {
+        window = ((window << 2) | code as u64) & mask;
+        if i + 1 >= k {
+            kmers.insert(window);
+        }
+    }
+    kmers
+}
+
+fn jaccard_distance(a: &HashSet<u64>, b: &HashSet<u64>) -> f32 {
+    let union = a.union(b).count();
+    if union == 0 {
+        return 0.0
+    }
+    1.0 - a.intersection(b).count() as f32 / union as f32
+}
+
+impl AgeEstimator for AgeEstimatorKind {
+    fn estimate(&self, genome: &Genome, messages: &[Message], previous: f32) -> f32 {
+        match self {
+            AgeEstimatorKind::RawSum => messages.iter().map(|m| m.difference).sum(),
+            AgeEstimatorKind::MeanPerMessage => mean_difference(messages),
+            AgeEstimatorKind::DistanceWeighted => {
+                let weights: Vec<f32> = messages.iter().map(|m| 1.0 / (1.0 + m.distance)).collect();
+                let total: f32 = weights.iter().sum();
+                if total == 0.0 {
+                    return 0.0
+                }
+                messages.iter().zip(&weights).map(|(m, w)| m.difference * w).sum::<f32>() / total
+            },
+            AgeEstimatorKind::KmerJaccard { k } => {
+                if messages.is_empty() {
+                    return 0.0
+                }
+                let own = kmers(genome, *k);
+                messages.iter().map(
+                    |m| jaccard_distance(&own, &kmers(m.genome, *k))
+                ).sum::<f32>() / messages.len() as f32
+            },
+            AgeEstimatorKind::MovingAverage { alpha } => {
+                alpha * mean_difference(messages) + (1.0 - alpha) * previous
+            }
+        }
+    }
+}
@@ -0,0 +1,135 @@
+use std::borrow::Cow;
+use std::error::Error;
+use std::fmt;
+use rand::prelude::*;
+use serde::{Deserialize, Serialize};
+use crate::{gene_mutation, Boundary, Genome, InvalidBase, Location, Settings};
+
+// a cell present when the organism is created
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Founder {
+    pub location: Location,
+    pub genome: Genome,
+    pub age: f32,
+    pub senescent: bool
+}
+
+// how the tissue looks at step 0
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub enum InitialState {
+    // a single cell at the centre carrying the reference genome, or one on each seed of the mask
+    #[default]
+    Zygote,
+    // exactly these cells
+    Founders(Vec<Founder>),
+    // each site of the arena holds a cell with the reference genome with probability fraction
+    RandomFill { fraction: f32 },
+    // a randomly filled adult tissue whose cells are already age old and have had each of
+    // their bases mutated with probability burden
+    PreAged { fraction: f32, age: f32, burden: f32 },
+    // a full arena, the left half carrying the reference genome and the right half a clone
+    // that has had each base mutated with probability divergence
+    TwoClones { divergence: f32 }
+}
+
+// why an organism couldn't be set up from the settings and founders given
+#[derive(Debug, Clone, PartialEq)]
+pub enum InitError {
+    Genome(InvalidBase),
+    FounderOffArena(Location),
+    DuplicateFounder(Location),
+    // a bounded arena of this side is too small for the mask laid over it
+    MaskTooLarge { width: usize, height: usize, length: usize },
+    // the message kernel never falls below KERNEL_CUTOFF
+    KernelRadius(f32)
+}
+
+impl fmt::Display for InitError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InitError::Genome(e) => write!(f, "invalid genome: {}", e),
+            InitError::FounderOffArena(l) => write!(f, "founder at ({}, {}, {}) is not a site of the arena", l.x, l.y, l.z),
+            InitError::DuplicateFounder(l) => write!(f, "two founders at ({}, {}, {})", l.x, l.y, l.z),
+            InitError::MaskTooLarge { width, height, length } => write!(
+                f, "a {}x{} mask does not fit in an arena of length {}", width, height, length
+            ),
+            InitError::KernelRadius(radius) => write!(f, "the message kernel's radius {} is not finite", radius)
+        }
+    }
+}
+
+impl Error for InitError {}
+
+impl From<InvalidBase> for InitError {
+    fn from(e: InvalidBase) -> Self { InitError::Genome(e) }
+}
+
+impl Founder {
+    fn new(location: Location, genome: Genome) -> Founder {
+        Founder { location, genome, age: 0.0, senescent: false }
+    }
+}
+
+// every site of the arena in row and layer order, leaving out those off the mask. an open
+// arena has no sites of its own, so the length x length block around the origin is used
+fn arena(settings: &Settings) -> Vec<Location> {
+    let offset = if settings.boundary == Boundary::Open { (settings.length / 2) as i32 } else { 0 };
+    let length = settings.length as i32;
+    let depth = settings.lattice.depth(settings.length) as i32;
+    let layer_offset = if depth > 1 { offset } else { 0 };
+    (0..depth).flat_map(move |z| (0..length).flat_map(
+        move |y| (0..length).map(move |x| Location { x: x - offset, y: y - offset, z: z - layer_offset })
+    )).filter(|site| settings.mask.as_ref().is_none_or(|mask| mask.allows(site))).collect()
+}
+
+impl InitialState {
+    // the cells to start from, any randomness drawn from rng
+    pub fn founders<R: Rng + ?Sized>(&self, settings: &Settings, rng: &mut R) -> Result<Vec<Founder>, InvalidBase> {
+        let reference = settings.reference()?;
+        let model = &settings.substitution;
+        let silent = settings.silent_mutations;
+        Ok(match self {
+            InitialState::Zygote => {
+                // an open arena has no centre, so growth starts from the origin
+                let centre = if settings.boundary == Boundary::Open { 0 } else { (settings.length / 2) as i32 };
+                let layer = if settings.lattice.dimensions() == 3 { centre } else { 0 };
+                let sites = match &settings.mask {
+                    // seeds drawn on a mask all start in the middle layer
+                    Some(mask) if !mask.seeds().is_empty() => mask.seeds().iter().map(
+                        |seed| Location { z: layer, ..seed.clone() }
+                    ).collect(),
+                    // a mask might leave the centre outside the tissue
+                    Some(mask) => vec![mask.nearest(&Location { x: centre, y: centre, z: layer })],
+                    None => vec![Location { x: centre, y: centre, z: layer }]
+                };
+                sites.into_iter().map(|site| Founder::new(site, reference.clone())).collect()
+            },
+            InitialState::Founders(founders) => founders.clone(),
+            InitialState::RandomFill { fraction } => arena(settings).into_iter().filter(
+                |_| rng.gen::<f32>() < *fraction
+            ).map(|site| Founder::new(site, reference.clone())).collect(),
+            InitialState::PreAged { fraction, age, burden } => {
+                let sites: Vec<Location> = arena(settings).into_iter().filter(|_| rng.gen::<f32>() < *fraction).collect();
+                sites.into_iter().map(|site| {
+                    let mut genome = Cow::Borrowed(&reference);
+                    gene_mutation(&mut genome, *burden, model, silent, rng);
+                    Founder { location: site, genome: genome.into_owned(), age: *age, senescent: false }
+                }).collect()
+            },
+            InitialState::TwoClones { divergence } => {
+                let mut variant = Cow::Borrowed(&reference);
+                gene_mutation(&mut variant, *divergence, model, silent, rng);
+                let variant = variant.into_owned();
+                // clones split at the middle of the arena along x
+                let sites = arena(settings);
+                let left = sites.iter().map(|site| site.x).min().unwrap_or(0);
+                let right = sites.iter().map(|site| site.x).max().unwrap_or(0);
+                let middle = (left + right).div_euclid(2);
+                sites.into_iter().map(|site| {
+                    let genome = if site.x <= middle { reference.clone() } else { variant.clone() };
+                    Founder::new(site, genome)
+                }).collect()
+            }
+        })
+    }
+}
@@ -0,0 +1,215 @@
+use std::fmt;
+use std::str::FromStr;
+use rand::prelude::*;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use crate::BASES;
+
+const BASES_PER_WORD: usize = 32;
+// the low bit of every 2-bit base
+const LOW_BITS: u64 = 0x5555_5555_5555_5555;
+
+// a genome stored as 2-bit codes, indices into BASES, packed 32 to a u64.
+// bits past the last base are always zero so whole words can be compared.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct Genome {
+    words: Vec<u64>,
+    len: usize
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidBase(pub char);
+
+impl fmt::Display for InvalidBase {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "'{}' is not one of G, C, A or T", self.0)
+    }
+}
+
+impl std::error::Error for InvalidBase {}
+
+pub fn base_code(base: u8) -> Option<u8> {
+    BASES.iter().position(|b| *b == base).map(|code| code as u8)
+}
+
+impl Genome {
+    pub fn random<R: Rng + ?Sized>(len: usize, rng: &mut R) -> Genome {
+        let mut genome = Genome::default();
+        for _ in 0..len {
+            genome.push_code(rng.gen_range(0..4));
+        }
+        genome
+    }
+
+    pub fn from_codes(codes: &[u8]) -> Genome {
+        let mut genome = Genome::default();
+        for code in codes {
+            genome.push_code(*code);
+        }
+        genome
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // code of base i, its index in BASES
+    pub fn code(&self, i: usize) -> u8 {
+        assert!(i < self.len, "base {} of a genome of length {}", i, self.len);
+        ((self.words[i / BASES_PER_WORD] >> (2 * (i % BASES_PER_WORD))) & 3) as u8
+    }
+
+    pub fn base(&self, i: usize) -> u8 {
+        BASES[self.code(i) as usize]
+    }
+
+    pub fn set_code(&mut self, i: usize, code: u8) {
+        assert!(i < self.len, "base {} of a genome of length {}", i, self.len);
+        let shift = 2 * (i % BASES_PER_WORD);
+        let word = &mut self.words[i / BASES_PER_WORD];
+        *word = (*word & !(3 << shift)) | ((code as u64 & 3) << shift);
+    }
+
+    pub fn set_base(&mut self, i: usize, base: u8) {
+        self.set_code(i, base_code(base).expect("not a base"));
+    }
+
+    pub fn push_code(&mut self, code: u8) {
+        if self.len == self.words.len() * BASES_PER_WORD {
+            self.words.push(0);
+        }
+        self.len += 1;
+        self.set_code(self.len - 1, code);
+    }
+
+    pub fn codes(&self) -> impl Iterator<Item = u8> + '_ {
+        (0..self.len).map(|i| self.code(i))
+    }
+
+    pub fn bases(&self) -> impl Iterator<Item = u8> + '_ {
+        self.codes().map(|code| BASES[code as usize])
+    }
+
+    // number of differing sites, each base of a length difference counting as one
+    pub fn hamming(&self, other: &Genome) -> u32 {
+        let shared = self.len.min(other.len);
+        let full = shared / BASES_PER_WORD;
+        let mut mismatches: u32 = self.words[..full].iter().zip(&other.words[..full]).map(|(a, b)| {
+            let x = a ^ b;
+            ((x | (x >> 1)) & LOW_BITS).count_ones()
+        }).sum();
+        let rest = shared % BASES_PER_WORD;
+        if rest > 0 {
+            let x = (self.words[full] ^ other.words[full]) & ((1u64 << (2 * rest)) - 1);
+            mismatches += ((x | (x >> 1)) & LOW_BITS).count_ones();
+        }
+        mismatches + self.len.abs_diff(other.len) as u32
+    }
+}
+
+impl FromStr for Genome {
+    type Err = InvalidBase;
+
+    fn from_str(s: &str) -> Result<Genome, InvalidBase> {
+        let mut genome = Genome::default();
+        for c in s.chars() {
+            let code = u8::try_from(c).ok().and_then(base_code).ok_or(InvalidBase(c))?;
+            genome.push_code(code);
+        }
+        Ok(genome)
+    }
+}
+
+impl fmt::Display for Genome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s: String = self.bases().map(|b| b as char).collect();
+        f.write_str(&s)
+    }
+}
+
+// json gets the readable string form, binary formats the packed words
+impl Serialize for Genome {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.collect_str(self)
+        } else {
+            (self.len, &self.words).serialize(serializer)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Genome {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Genome, D::Error> {
+        if deserializer.is_human_readable() {
+            String::deserialize(deserializer)?.parse().map_err(serde::de::Error::custom)
+        } else {
+            let (len, words) = <(usize, Vec<u64>)>::deserialize(deserializer)?;
+            if words.len() != len.div_ceil(BASES_PER_WORD) {
+                return Err(serde::de::Error::custom("genome length does not match its words"))
+            }
+            // comparisons and hamming distances count on the bits past the last base being zero
+            let used = 2 * (len % BASES_PER_WORD);
+            if (used > 0) && words.last().is_some_and(|word| word >> used != 0) {
+                return Err(serde::de::Error::custom("genome has bits set past its last base"))
+            }
+            Ok(Genome { words, len })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn genome(s: &str) -> Genome {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn hamming_counts_every_word() {
+        let a = "GATTACA".repeat(10);
+        for len in [31, 32, 33, 64, 65] {
+            let mut b: Vec<u8> = a.as_bytes()[..len].to_vec();
+            // the last base of the genome, and the first of the last word
+            b[len - 1] = if b[len - 1] == b'G' { b'C' } else { b'G' };
+            let word = (len - 1) / BASES_PER_WORD * BASES_PER_WORD;
+            if word < len - 1 {
+                b[word] = if b[word] == b'G' { b'C' } else { b'G' };
+            }
+            let b = genome(std::str::from_utf8(&b).unwrap());
+            let a = genome(&a[..len]);
+            let expected = 1 + (word < len - 1) as u32;
+            assert_eq!(a.hamming(&b), expected, "length {}", len);
+            assert_eq!(b.hamming(&a), expected, "length {}", len);
+        }
+    }
+
+    #[test]
+    fn hamming_counts_length_difference() {
+        let a = genome(&"GATTACA".repeat(5));
+        let b = genome(&"GATTACA".repeat(5)[..33]);
+        assert_eq!(a.hamming(&b), 2);
+        assert_eq!(genome("").hamming(&b), 33);
+    }
+
+    #[test]
+    fn round_trips_through_text() {
+        let text = "GATTACA".repeat(7);
+        assert_eq!(genome(&text).to_string(), text);
+        assert_eq!("GATXACA".parse::<Genome>(), Err(InvalidBase('X')));
+    }
+
+    #[test]
+    fn binary_rejects_bits_past_the_last_base() {
+        let a = genome(&"GATTACA".repeat(5));
+        let bytes = bincode::serialize(&a).unwrap();
+        assert_eq!(bincode::deserialize::<Genome>(&bytes).unwrap(), a);
+        let mut words = a.words.clone();
+        *words.last_mut().unwrap() |= 1 << 63;
+        let bytes = bincode::serialize(&(a.len, words)).unwrap();
+        assert!(bincode::deserialize::<Genome>(&bytes).is_err());
+    }
+}
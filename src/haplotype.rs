@@ -0,0 +1,140 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use crate::{Genome, Mutation, SiteCounts, SiteMap};
+
+// a cell's handle on the genome it carries
+pub type HaplotypeId = u32;
+
+// every distinct genome in the organism stored once, with the number of cells carrying it and
+// how it lines up with the reference. cells share a haplotype until a mutation gives one of
+// them a genome of its own.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(from = "HaplotypeRecord")]
+pub struct HaplotypeTable {
+    genomes: Vec<Genome>,
+    counts: Vec<u32>,
+    maps: Vec<SiteMap>,
+    // slots whose haplotype was lost, reused before the table grows
+    free: Vec<HaplotypeId>,
+    // finds the slot a genome already has, rebuilt when a snapshot is loaded
+    #[serde(skip)]
+    index: HashMap<Genome, HaplotypeId>
+}
+
+#[derive(Deserialize)]
+struct HaplotypeRecord {
+    genomes: Vec<Genome>,
+    counts: Vec<u32>,
+    maps: Vec<SiteMap>,
+    free: Vec<HaplotypeId>
+}
+
+impl From<HaplotypeRecord> for HaplotypeTable {
+    fn from(record: HaplotypeRecord) -> Self {
+        let index = record.genomes.iter().zip(&record.counts).enumerate().filter(|(_, (_, c))| **c > 0).map(
+            |(id, (genome, _))| (genome.clone(), id as HaplotypeId)
+        ).collect();
+        HaplotypeTable { genomes: record.genomes, counts: record.counts, maps: record.maps, free: record.free, index }
+    }
+}
+
+impl HaplotypeTable {
+    pub fn get(&self, id: HaplotypeId) -> &Genome {
+        &self.genomes[id as usize]
+    }
+
+    pub fn count(&self, id: HaplotypeId) -> u32 {
+        self.counts[id as usize]
+    }
+
+    pub fn map(&self, id: HaplotypeId) -> &SiteMap {
+        &self.maps[id as usize]
+    }
+
+    // one more cell carries genome, lined up with the reference by map, sharing the slot of an
+    // identical haplotype if there is one
+    pub fn intern(&mut self, genome: Genome, map: SiteMap) -> HaplotypeId {
+        if let Some(id) = self.index.get(&genome) {
+            self.counts[*id as usize] += 1;
+            return *id
+        }
+        let id = match self.free.pop() {
+            Some(id) => {
+                self.genomes[id as usize] = genome.clone();
+                self.counts[id as usize] = 1;
+                self.maps[id as usize] = map;
+                id
+            },
+            None => {
+                self.genomes.push(genome.clone());
+                self.counts.push(1);
+                self.maps.push(map);
+                (self.genomes.len() - 1) as HaplotypeId
+            }
+        };
+        self.index.insert(genome, id);
+        id
+    }
+
+    // one more cell carries haplotype id
+    pub fn retain(&mut self, id: HaplotypeId) {
+        self.counts[id as usize] += 1;
+    }
+
+    // one fewer cell carries haplotype id, dropping the genome once none do
+    pub fn release(&mut self, id: HaplotypeId) {
+        let count = &mut self.counts[id as usize];
+        *count -= 1;
+        if *count == 0 {
+            let genome = std::mem::take(&mut self.genomes[id as usize]);
+            self.maps[id as usize] = SiteMap::default();
+            self.index.remove(&genome);
+            self.free.push(id);
+        }
+    }
+
+    // mutate the genome of one cell carrying haplotype id, which only gets a slot of its own
+    // if mutate actually changes it, keeping sites in step. returns the cell's haplotype
+    // afterwards and the number of mutations made.
+    pub fn mutate(
+        &mut self, id: HaplotypeId, sites: &mut SiteCounts, mutate: impl FnOnce(&mut Cow<Genome>) -> Vec<Mutation>
+    ) -> (HaplotypeId, usize) {
+        let mut genome = Cow::Borrowed(&self.genomes[id as usize]);
+        let mutations = mutate(&mut genome);
+        match genome {
+            Cow::Borrowed(_) => (id, mutations.len()),
+            Cow::Owned(genome) => {
+                let mut map = self.maps[id as usize].clone();
+                sites.mutate(&mut map, &mutations);
+                self.release(id);
+                // the same genome reached by another route may line up with the reference
+                // differently, and is counted the way the haplotype already carrying it does
+                if let Some(existing) = self.index.get(&genome) {
+                    let existing = &self.maps[*existing as usize];
+                    if *existing != map {
+                        sites.remove(&genome, &map);
+                        sites.add(&genome, existing);
+                    }
+                }
+                (self.intern(genome, map), mutations.len())
+            }
+        }
+    }
+
+    // number of distinct genomes carried by at least one cell
+    pub fn len(&self) -> usize {
+        self.genomes.len() - self.free.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // each haplotype still carried by a cell, with how many cells carry it
+    pub fn frequencies(&self) -> impl Iterator<Item = (HaplotypeId, &Genome, u32)> + '_ {
+        self.genomes.iter().zip(&self.counts).enumerate().filter(|(_, (_, c))| **c > 0).map(
+            |(id, (genome, count))| (id as HaplotypeId, genome, *count)
+        )
+    }
+}
@@ -0,0 +1,164 @@
+use std::sync::Arc;
+use rand::prelude::*;
+use serde::{Deserialize, Serialize};
+use crate::{Lattice, Settings};
+
+// probabilities below this are treated as zero, which bounds how far growstep looks for messages
+pub const KERNEL_CUTOFF: f32 = 1e-3;
+
+// how likely a cell is to hear a message from another cell a given distance away
+pub trait MessageKernel {
+    fn probability(&self, distance: f32) -> f32;
+    // distance beyond which the probability drops below KERNEL_CUTOFF
+    fn radius(&self) -> f32;
+}
+
+// the built-in kernels, selectable in Settings. every kernel gives 1 at distance 0
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum MessageKernelKind {
+    // exp(-distance / length_scale)
+    Exponential { length_scale: f32 },
+    // exp(-distance^2 / 2 sigma^2)
+    Gaussian { sigma: f32 },
+    // (1 + distance)^-exponent
+    PowerLaw { exponent: f32 },
+    // every cell within radius, nothing beyond
+    TopHat { radius: f32 },
+    // only the sites touching it on the lattice, out to Lattice::neighbour_radius
+    NearestNeighbour
+}
+
+impl Default for MessageKernelKind {
+    fn default() -> Self {
+        MessageKernelKind::Exponential { length_scale: 5.0 }
+    }
+}
+
+// a kernel laid on a lattice, which is what nearest neighbours are measured against
+pub struct LatticeKernel<'a> {
+    kernel: &'a MessageKernelKind,
+    lattice: Lattice
+}
+
+impl MessageKernelKind {
+    pub fn on(&self, lattice: Lattice) -> LatticeKernel<'_> {
+        LatticeKernel { kernel: self, lattice }
+    }
+}
+
+impl MessageKernel for LatticeKernel<'_> {
+    fn probability(&self, distance: f32) -> f32 {
+        match self.kernel {
+            MessageKernelKind::Exponential { length_scale } => (-distance / length_scale).exp(),
+            MessageKernelKind::Gaussian { sigma } => (-distance.powi(2) / (2.0 * sigma.powi(2))).exp(),
+            MessageKernelKind::PowerLaw { exponent } => (1.0 + distance).powf(-exponent),
+            MessageKernelKind::TopHat { radius } => if distance <= *radius { 1.0 } else { 0.0 },
+            MessageKernelKind::NearestNeighbour => if distance <= self.lattice.neighbour_radius() { 1.0 } else { 0.0 }
+        }
+    }
+
+    fn radius(&self) -> f32 {
+        match self.kernel {
+            MessageKernelKind::Exponential { length_scale } => -length_scale * KERNEL_CUTOFF.ln(),
+            MessageKernelKind::Gaussian { sigma } => sigma * (-2.0 * KERNEL_CUTOFF.ln()).sqrt(),
+            MessageKernelKind::PowerLaw { exponent } => KERNEL_CUTOFF.powf(-1.0 / exponent) - 1.0,
+            MessageKernelKind::TopHat { radius } => *radius,
+            MessageKernelKind::NearestNeighbour => self.lattice.neighbour_radius()
+        }
+    }
+}
+
+// how the messages a cell hears each step are chosen
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub enum Sampling {
+    // every cell within the kernel's radius is heard with the kernel's probability
+    #[default]
+    AllPairs,
+    // each cell hears a fixed number of messages, or a Poisson number with that mean,
+    // with the sources drawn from the kernel. models a finite receptor capacity and
+    // keeps the cost per cell independent of organism size
+    Budget { messages: u32, poisson: bool }
+}
+
+// the kernel evaluated once per step for every grid offset within reach, so growstep
+// only looks up distances and probabilities while scanning the sites around a cell
+pub struct KernelTable {
+    reach: i32,
+    // reach along z, 0 on a flat lattice
+    depth: i32,
+    // the offsets within the radius in grid order, with their distance and probability
+    within: Vec<(i32, i32, i32, f32, f32)>,
+    // offsets that can be heard from and the running total of their probabilities
+    sources: Vec<(i32, i32, i32, f32)>,
+    cumulative: Vec<f64>
+}
+
+impl KernelTable {
+    pub fn new(
+        kernel: &impl MessageKernel, reach: i32, depth: i32, distance: impl Fn(i32, i32, i32) -> Option<f32>
+    ) -> KernelTable {
+        let radius = kernel.radius();
+        let mut within = vec![];
+        let mut sources = vec![];
+        let mut cumulative = vec![];
+        let mut total = 0.0;
+        for dz in -depth..=depth {
+            for dy in -reach..=reach {
+                for dx in -reach..=reach {
+                    let entry = distance(dx, dy, dz).filter(|d| *d <= radius).map(|d| (d, kernel.probability(d)));
+                    if let Some((d, p)) = entry {
+                        within.push((dx, dy, dz, d, p));
+                    }
+                    if let Some((d, p)) = entry.filter(|(_, p)| *p > 0.0) {
+                        total += p as f64;
+                        sources.push((dx, dy, dz, d));
+                        cumulative.push(total);
+                    }
+                }
+            }
+        }
+        KernelTable { reach, depth, within, sources, cumulative }
+    }
+
+    pub fn reach(&self) -> i32 {
+        self.reach
+    }
+
+    pub fn depth(&self) -> i32 {
+        self.depth
+    }
+
+    pub fn within(&self) -> &[(i32, i32, i32, f32, f32)] {
+        &self.within
+    }
+
+    // an offset drawn with probability proportional to the kernel, with its distance. None
+    // if the kernel can't be heard from anywhere, not even the cell's own site
+    pub fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Option<(i32, i32, i32, f32)> {
+        let total = *self.cumulative.last()?;
+        let draw = rng.gen::<f64>() * total;
+        let k = self.cumulative.partition_point(|c| *c <= draw).min(self.sources.len() - 1);
+        Some(self.sources[k])
+    }
+}
+
+// the table of the settings an organism grows under, kept from one step to the next and
+// only rebuilt if the settings change. it is only ever a copy of what the settings give,
+// so it is never saved and never makes two organisms differ
+#[derive(Clone, Default)]
+pub(crate) struct TableCache(Option<Arc<(Settings, KernelTable)>>);
+
+impl TableCache {
+    pub fn get(&mut self, settings: &Settings) -> Arc<(Settings, KernelTable)> {
+        match &self.0 {
+            Some(cached) if cached.0 == *settings => cached.clone(),
+            _ => self.0.insert(Arc::new((settings.clone(), settings.kernel_table()))).clone()
+        }
+    }
+}
+
+impl PartialEq for TableCache {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
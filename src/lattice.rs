@@ -0,0 +1,101 @@
+use serde::{Deserialize, Serialize};
+use crate::Location;
+
+// the geometry of the sites cells sit on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Lattice {
+    // square sites, each touching the 8 around it, at euclidean distances
+    #[default]
+    SquareMoore,
+    // square sites, each touching the 4 sharing an edge with it, at manhattan distances
+    SquareVonNeumann,
+    // hexagonal sites in axial coordinates, x along a row and y along the rows, each
+    // touching 6 others all one apart
+    Hexagonal,
+    // cubic sites filling a length x length x length arena, each touching the 26 around it,
+    // at euclidean distances
+    CubicMoore,
+    // cubic sites, each touching the 6 sharing a face with it, at euclidean distances like
+    // CubicMoore since the two only differ in which sites count as touching
+    CubicVonNeumann
+}
+
+// 1 / sqrt(3), the circumradius of a hexagon one wide
+const FRAC_1_SQRT_3: f32 = 0.577_350_27;
+
+const MOORE: [(i32, i32); 8] = [(0, 1), (1, 0), (1, 1), (1, -1), (0, -1), (-1, 0), (-1, -1), (-1, 1)];
+const VON_NEUMANN: [(i32, i32); 4] = [(0, 1), (1, 0), (0, -1), (-1, 0)];
+const HEXAGONAL: [(i32, i32); 6] = [(1, 0), (1, -1), (0, -1), (-1, 0), (-1, 1), (0, 1)];
+const CUBIC_VON_NEUMANN: [(i32, i32, i32); 6] = [(0, 1, 0), (1, 0, 0), (0, -1, 0), (-1, 0, 0), (0, 0, 1), (0, 0, -1)];
+
+impl Lattice {
+    pub fn dimensions(&self) -> u32 {
+        match self {
+            Lattice::CubicMoore | Lattice::CubicVonNeumann => 3,
+            _ => 2
+        }
+    }
+
+    // number of layers of sites along z in an arena of side length
+    pub fn depth(&self, length: usize) -> usize {
+        if self.dimensions() == 3 { length } else { 1 }
+    }
+
+    pub fn neighbours(&self, location: &Location) -> Vec<Location> {
+        let diffs: Vec<(i32, i32, i32)> = match self {
+            Lattice::SquareMoore => MOORE.iter().map(|(dx, dy)| (*dx, *dy, 0)).collect(),
+            Lattice::SquareVonNeumann => VON_NEUMANN.iter().map(|(dx, dy)| (*dx, *dy, 0)).collect(),
+            Lattice::Hexagonal => HEXAGONAL.iter().map(|(dx, dy)| (*dx, *dy, 0)).collect(),
+            Lattice::CubicMoore => (-1..=1).flat_map(
+                |dz| (-1..=1).flat_map(move |dy| (-1..=1).map(move |dx| (dx, dy, dz)))
+            ).filter(|d| *d != (0, 0, 0)).collect(),
+            Lattice::CubicVonNeumann => CUBIC_VON_NEUMANN.to_vec()
+        };
+        diffs.iter().map(
+            |(dx, dy, dz)| Location { x: location.x + dx, y: location.y + dy, z: location.z + dz }
+        ).collect()
+    }
+
+    // distance to the furthest of the sites a cell touches
+    pub fn neighbour_radius(&self) -> f32 {
+        let origin = Location { x: 0, y: 0, z: 0 };
+        self.neighbours(&origin).iter().map(|site| self.distance(site.x, site.y, site.z)).fold(0.0, f32::max)
+    }
+
+    // distance covered by an offset of dx, dy, dz sites, neighbours being 1 apart
+    pub fn distance(&self, dx: i32, dy: i32, dz: i32) -> f32 {
+        match self {
+            Lattice::SquareMoore => (dx.pow(2) as f32 + dy.pow(2) as f32).powf(0.5),
+            Lattice::SquareVonNeumann => (dx.abs() + dy.abs()) as f32,
+            Lattice::Hexagonal => ((dx.pow(2) + dx * dy + dy.pow(2)) as f32).powf(0.5),
+            Lattice::CubicMoore | Lattice::CubicVonNeumann => {
+                (dx.pow(2) as f32 + dy.pow(2) as f32 + dz.pow(2) as f32).powf(0.5)
+            }
+        }
+    }
+
+    // how many sites along either axis it can take to cover a distance. a row of hexagons is
+    // sheared against the next, so going straight across takes more rows than the distance
+    pub fn span(&self, distance: f32) -> f32 {
+        match self {
+            Lattice::Hexagonal => distance * 2.0 * FRAC_1_SQRT_3,
+            _ => distance
+        }
+    }
+
+    // centre of a site on the plane, neighbours being 1 apart. cubic sites are seen down z
+    pub fn position(&self, location: &Location) -> (f32, f32) {
+        match self {
+            Lattice::Hexagonal => (location.x as f32 + location.y as f32 / 2.0, location.y as f32 * 1.5 * FRAC_1_SQRT_3),
+            _ => (location.x as f32, location.y as f32)
+        }
+    }
+
+    // furthest a site reaches from its centre along either axis of the plane
+    pub fn extent(&self) -> f32 {
+        match self {
+            Lattice::Hexagonal => FRAC_1_SQRT_3,
+            _ => 0.5
+        }
+    }
+}
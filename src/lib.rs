@@ -1,203 +1,704 @@
-use std::f32::consts::E;
-use std::iter::zip;
+use std::collections::BTreeMap;
 use rand::prelude::*;
-use rand_distr::{Exp, Distribution};
-use yew::{Properties};
+use rand_chacha::ChaCha8Rng;
+use rand_distr::{Poisson, Distribution};
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
 
+mod snapshot;
+pub use snapshot::{SnapshotError, SNAPSHOT_VERSION};
+mod mutation;
+pub use mutation::{gene_mutation, indel_mutation, Mutation, SubstitutionModel, BASES};
+mod alignment;
+pub use alignment::{Alignment};
+mod estimator;
+pub use estimator::{AgeEstimator, AgeEstimatorKind, Message};
+mod kernel;
+pub use kernel::{MessageKernel, MessageKernelKind, LatticeKernel, KernelTable, Sampling, KERNEL_CUTOFF};
+use kernel::{TableCache};
+mod division;
+pub use division::{DivisionPolicy, DivisionPolicyKind};
+mod senescence;
+pub use senescence::{Senescence};
+mod genome;
+pub use genome::{Genome, InvalidBase};
+mod haplotype;
+pub use haplotype::{HaplotypeId, HaplotypeTable};
+mod sites;
+pub use sites::{SiteCounts, SiteMap};
+mod death;
+pub use death::{Death};
+mod boundary;
+pub use boundary::{Boundary};
+mod lattice;
+pub use lattice::{Lattice};
+mod mask;
+pub use mask::{Mask, MaskError};
+mod founders;
+pub use founders::{Founder, InitError, InitialState};
+mod lineage;
+pub use lineage::{CellId, CellRecord, Lineage};
+mod occupancy;
+use occupancy::{Occupancy};
 
 
-#[derive(Clone, PartialEq, Properties)]
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "web", derive(yew::Properties))]
+#[serde(default)]
 pub struct Settings {
+    // side of the square arena, a cube on a cubic lattice. an open boundary has no arena,
+    // there it is the block around the origin initial states fill and mean_age averages over
     pub length: usize,
+    pub boundary: Boundary,
+    pub lattice: Lattice,
+    // restricts growth to the tissue sites of a mask, None leaves the whole arena open to it
+    pub mask: Option<Mask>,
+    pub initial: InitialState,
     pub genome: String,
     pub mutation_rate: f32,
     pub growth_rate: f32,
+    pub substitution: SubstitutionModel,
+    // allow a mutation to redraw the base it replaces
+    pub silent_mutations: bool,
+    // per-site probabilities of losing a base or gaining one after it, each step
+    pub insertion_rate: f32,
+    pub deletion_rate: f32,
+    pub alignment: Alignment,
+    pub age_estimator: AgeEstimatorKind,
+    pub kernel: MessageKernelKind,
+    // furthest a message travels along an axis of an open arena, however wide the kernel
+    pub max_reach: usize,
+    pub sampling: Sampling,
+    pub division: DivisionPolicyKind,
+    pub senescence: Senescence,
+    pub death: Death,
     pub seed: u32
 }
 
-#[derive(Clone, PartialEq, Properties)]
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "web", derive(yew::Properties))]
 pub struct Organism {
     pub coordinates: Vec<Location>,
     pub ages: Vec<f32>,
     pub senescent: Vec<bool>,
-    pub genomes: Vec<String>,
+    // each cell's handle into haplotypes, where the genomes themselves are kept
+    pub genomes: Vec<HaplotypeId>,
+    pub haplotypes: HaplotypeTable,
+    // each cell's handle into lineage, which records every cell there has ever been
+    pub ids: Vec<CellId>,
+    pub lineage: Lineage,
+    pub sites: SiteCounts,
     pub settings: Settings,
     pub size: u32,
-    pub samplesize: u32
+    pub samplesize: u32,
+    pub step: u32,
+    // number of mutations made and cells lost during the last growstep
+    pub mutations: u32,
+    pub deaths: u32,
+    // every stochastic decision draws from this, so a given seed gives the same trajectory
+    pub rng: ChaCha8Rng,
+    #[serde(skip)]
+    table: TableCache
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Location {
     pub x: i32,
-    pub y: i32
+    pub y: i32,
+    // always 0 on a flat lattice
+    #[serde(default)]
+    pub z: i32
 }
 
 impl Location {
-    pub fn get_neighbours(&self) -> Vec<Location> {
-        let diffs: Vec<(i32, i32)> = [
-            (0, 1), (1, 0), (1, 1), (1, -1),
-            (0, -1), (-1, 0), (-1, -1), (-1, 1)
-        ].to_vec();
-        diffs.iter().map(
-        |l| Location { x: self.x + l.0, y: self.y + l.1}
-        ).filter(
-        |l| (l.x >= 0) & (l.y >= 0)
-        ).collect()
+    // the sites touching this one on the lattice, which of them exist is up to the boundary
+    pub fn get_neighbours(&self, lattice: &Lattice) -> Vec<Location> {
+        lattice.neighbours(self)
     }
 
-    // to choose neighbour to sample from you need to:
-    // 1. sample a surrounding ring of cells with p(ring) decreasing exponentially with distance
-    // 2. randomly choose a cell in the ring
-    pub fn sample_neighbour(&self) -> Location {
-        let exp = Exp::new(1.0).unwrap();
-        let row: i32 = 1 + exp.sample(&mut rand::thread_rng()) as i32;
-        let mut diffs: Vec<(i32, i32)> = [].to_vec();
-        for r in -row..=row {diffs.push((-row, r))};
-        for r in -row..=row {diffs.push((row, r))};
-        for r in -row..=row {diffs.push((r, -row))};
-        for r in -row..=row {diffs.push((r, row))};
-        let index: usize = rand::thread_rng().gen_range(0..diffs.len());
-        Location {
-            x: self.x + diffs[index].0,
-            y: self.y + diffs[index].1
-        }
+    // choose a site to hear from, with p(site) following the message kernel, and its distance.
+    // None if the kernel has nowhere to hear from
+    pub fn sample_neighbour<R: Rng + ?Sized>(&self, table: &KernelTable, rng: &mut R) -> Option<(Location, f32)> {
+        let (dx, dy, dz, distance) = table.sample(rng)?;
+        Some((Location { x: self.x + dx, y: self.y + dy, z: self.z + dz }, distance))
     }
 
 }
 
 impl PartialEq for Location {
     fn eq(&self, other: &Self) -> bool {
-        (self.x == other.x) & (self.y == other.y)
+        (self.x == other.x) & (self.y == other.y) & (self.z == other.z)
     }
 }
 impl Eq for Location {}
 
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            length: 20,
+            boundary: Boundary::HardWall,
+            lattice: Lattice::SquareMoore,
+            mask: None,
+            initial: InitialState::Zygote,
+            genome: "GATTACA".to_string(),
+            mutation_rate: 0.00016,
+            growth_rate: 0.01,
+            substitution: SubstitutionModel::JC69,
+            silent_mutations: false,
+            insertion_rate: 0.0,
+            deletion_rate: 0.0,
+            alignment: Alignment::Hamming,
+            age_estimator: AgeEstimatorKind::RawSum,
+            kernel: MessageKernelKind::default(),
+            max_reach: 64,
+            sampling: Sampling::AllPairs,
+            division: DivisionPolicyKind::default(),
+            senescence: Senescence::default(),
+            death: Death::default(),
+            seed: 1234
+        }
+    }
+}
+
 impl Settings {
-    pub fn init_organism(&self) -> Organism {
-        let coords = [Location {
-            x:(self.length / 2) as i32, 
-            y:(self.length / 2) as i32
-        }].to_vec();
-        println!("{:?}", coords);
+    // the genome every cell starts from, packed
+    pub fn reference(&self) -> Result<Genome, InvalidBase> {
+        self.genome.parse()
+    }
+
+    // whether the settings describe something that can be simulated
+    fn check(&self) -> Result<(), InitError> {
+        let radius = self.kernel.on(self.lattice).radius();
+        if !radius.is_finite() {
+            return Err(InitError::KernelRadius(radius))
+        }
+        if let Some(mask) = self.mask.as_ref().filter(|_| self.boundary != Boundary::Open) {
+            if (mask.width() > self.length) | (mask.height() > self.length) {
+                return Err(InitError::MaskTooLarge { width: mask.width(), height: mask.height(), length: self.length })
+            }
+        }
+        Ok(())
+    }
+
+    // the kernel laid out over every offset a message can come from
+    pub fn kernel_table(&self) -> KernelTable {
+        let kernel = self.kernel.on(self.lattice);
+        let depth = self.lattice.depth(self.length);
+        let reach = self.boundary.reach(self.lattice.span(kernel.radius()), self.length, self.max_reach);
+        // a flat lattice only has the one layer to hear from
+        let reach_z = if depth > 1 { reach } else { 0 };
+        let (boundary, length) = (self.boundary, self.length);
+        KernelTable::new(&kernel, reach, reach_z, |x, y, z| {
+            let distinct = boundary.distinct(x, length) & boundary.distinct(y, length) & boundary.distinct(z, depth);
+            distinct.then(|| distance_calc(&Location { x, y, z }, &Location { x: 0, y: 0, z: 0 }, self))
+        })
+    }
+
+    pub fn init_organism(&self) -> Result<Organism, InitError> {
+        self.check()?;
+        // the founders draw from the simulation rng, so a seed fixes the starting tissue too
+        let mut rng = ChaCha8Rng::seed_from_u64(self.seed as u64);
+        let founders = self.initial.founders(self, &mut rng)?;
+        self.grow_from(founders, rng)
+    }
+
+    // start an organism from exactly these cells, whatever initial says
+    pub fn init_with_founders(&self, founders: Vec<Founder>) -> Result<Organism, InitError> {
+        self.check()?;
+        self.grow_from(founders, ChaCha8Rng::seed_from_u64(self.seed as u64))
+    }
+
+    fn grow_from(&self, founders: Vec<Founder>, rng: ChaCha8Rng) -> Result<Organism, InitError> {
+        let mut taken = Occupancy::new(
+            self.length, self.lattice.depth(self.length), self.boundary, self.mask.as_ref(), &[]
+        );
+        let mut haplotypes = HaplotypeTable::default();
+        let mut sites = SiteCounts::new(self.reference()?);
+        let (mut coordinates, mut ages, mut senescent, mut genomes) = (vec![], vec![], vec![], vec![]);
+        let mut lineage = Lineage::default();
+        let mut ids = vec![];
+        for (i, founder) in founders.into_iter().enumerate() {
+            if taken.resolve(&founder.location).as_ref() != Some(&founder.location) {
+                return Err(InitError::FounderOffArena(founder.location))
+            }
+            if taken.get(&founder.location).is_some() {
+                return Err(InitError::DuplicateFounder(founder.location))
+            }
+            taken.insert(&founder.location, i);
+            let map = sites.map(&founder.genome, self);
+            sites.add(&founder.genome, &map);
+            genomes.push(haplotypes.intern(founder.genome, map));
+            coordinates.push(founder.location);
+            ages.push(founder.age);
+            senescent.push(founder.senescent);
+            ids.push(lineage.found(0));
+        }
         // build base plot
-        Organism {
-            coordinates: coords.clone(),
-            ages: vec![0.0; coords.len()],
-            senescent: vec![false; coords.len()],
-            genomes: vec![self.genome.clone(); coords.len()],
+        Ok(Organism {
+            size: coordinates.len() as u32,
+            coordinates,
+            ages,
+            senescent,
+            genomes,
+            haplotypes,
+            ids,
+            lineage,
+            sites,
             settings: self.clone(),
-            size: 1,
-            samplesize: 10
-        }
+            samplesize: 10,
+            step: 0,
+            mutations: 0,
+            deaths: 0,
+            rng,
+            table: TableCache::default()
+        })
     }
 }
 
-// calculate the euclidean distance between two points in the grid as a flat array
-fn distance_calc(j: &Location, i: &Location) -> f32 {
-    ((j.x - i.x).pow(2) as f32 + 
-    (j.y - i.y).pow(2) as f32 ).powf(0.5)
+// calculate the distance between two points in the grid under the lattice's metric,
+// going the short way around a periodic arena
+fn distance_calc(j: &Location, i: &Location, settings: &Settings) -> f32 {
+    let depth = settings.lattice.depth(settings.length);
+    let (dx, dy, dz) = settings.boundary.displacement(j, i, settings.length, depth);
+    settings.lattice.distance(dx, dy, dz)
 }
 
-// calculate the difference between rna signals
-fn difference_rna(g1: &String, g2: &String) -> f32 {
-        zip(g1.chars().collect::<Vec<char>>(), g2.chars().collect::<Vec<char>>()).map(
-        |(x, y)| if x == y { 0 } else { 1 }
-        ).collect::<Vec<u32>>().iter().sum::<u32>() as f32
-}
 
-// routine to mutate genes
-fn gene_mutation(gene: String, mutation_rate: f32) -> String {
-    let mut rng = thread_rng();
-    let thresh = rng.gen::<f32>();
-    gene.chars().collect::<Vec<char>>().iter().map(
-        |l| {
-            if mutation_rate > thresh { 
-                let bases = "GCAT".chars();
-                bases.choose(&mut rng).unwrap() 
-            } 
-            else { 
-                l.clone() 
-            }
-        }
-    ).collect::<String>().to_string()
+// draws of an empty site allowed per message in Sampling::Budget before it is lost
+const MAX_SAMPLING_ATTEMPTS: u32 = 1000;
+
+// what a cell made of this step's messages, and its random stream for the rest of the step
+struct Reading {
+    age: f32,
+    heard: u32,
+    senescent: bool,
+    threshsplit: f32,
+    rng: ChaCha8Rng
 }
 
+// keep the per-cell entries of cells that are still alive
+fn survivors<T>(values: Vec<T>, alive: &[bool]) -> Vec<T> {
+    values.into_iter().zip(alive).filter(|(_, a)| **a).map(|(v, _)| v).collect()
+}
 
 impl Organism {
+    // averaged over every site of the arena, or of the tissue with a mask, empty ones counting
+    // as age 0
     pub fn mean_age(&self) -> f32 {
-        self.ages.iter().sum::<f32>() / self.settings.length.pow(2) as f32
+        let depth = self.settings.lattice.depth(self.settings.length);
+        let sites = match &self.settings.mask {
+            Some(mask) if self.settings.boundary == Boundary::Open => mask.area() * depth,
+            Some(mask) => mask.area_within(self.settings.length) * depth,
+            None => self.settings.length.pow(self.settings.lattice.dimensions())
+        };
+        self.ages.iter().sum::<f32>() / sites.max(1) as f32
     }
 
+    // averaged over the cells alone
+    pub fn mean_cell_age(&self) -> f32 {
+        self.ages.iter().sum::<f32>() / self.ages.len().max(1) as f32
+    }
+
+    // left and top edges and side, on the plane of Lattice::position, of the square to draw:
+    // around the arena, or with an open boundary around the organism
+    pub fn frame(&self) -> (f32, f32, f32) {
+        let lattice = &self.settings.lattice;
+        let last = self.settings.length.max(1) as i32 - 1;
+        let corners = [(0, 0), (last, 0), (0, last), (last, last)].map(|(x, y)| Location { x, y, z: 0 });
+        let sites = if self.settings.boundary == Boundary::Open { &self.coordinates[..] } else { &corners[..] };
+        let (mut left, mut right, mut top, mut bottom) = (f32::MAX, f32::MIN, f32::MAX, f32::MIN);
+        for site in sites {
+            let (x, y) = lattice.position(site);
+            (left, right, top, bottom) = (left.min(x), right.max(x), top.min(y), bottom.max(y));
+        }
+        if sites.is_empty() {
+            (left, right, top, bottom) = (0.0, 0.0, 0.0, 0.0);
+        }
+        let side = (right - left).max(bottom - top) + 2.0 * lattice.extent();
+        // centre the sites along the shorter axis
+        let left = (left + right - side) / 2.0;
+        let top = (top + bottom - side) / 2.0;
+        (left, top, side)
+    }
+
+    // cells in layer z of a 3d organism
+    pub fn slice(&self, z: i32) -> Vec<usize> {
+        (0..self.coordinates.len()).filter(|i| self.coordinates[*i].z == z).collect()
+    }
+
+    // a 3d organism seen down z: every occupied column, at z 0, with the mean age of its
+    // cells and how many there are
+    pub fn projection(&self) -> Vec<(Location, f32, u32)> {
+        let mut columns: BTreeMap<(i32, i32), (f32, u32)> = BTreeMap::new();
+        for (location, age) in self.coordinates.iter().zip(&self.ages) {
+            let column = columns.entry((location.y, location.x)).or_insert((0.0, 0));
+            column.0 += age;
+            column.1 += 1;
+        }
+        columns.into_iter().map(
+            |((y, x), (ages, cells))| (Location { x, y, z: 0 }, ages / cells as f32, cells)
+        ).collect()
+    }
+
+    // the genome cell i carries
+    pub fn genome(&self, i: usize) -> &Genome {
+        self.haplotypes.get(self.genomes[i])
+    }
+
+    // every division so far as a newick tree, see Lineage::to_newick
+    pub fn to_newick(&self) -> String {
+        self.lineage.to_newick(self.step)
+    }
+
+    pub fn to_nexml(&self) -> String {
+        self.lineage.to_nexml(self.step)
+    }
+
+    pub fn senescent_count(&self) -> u32 {
+        self.senescent.iter().filter(|s| **s).count() as u32
+    }
+
+    // entropy of each site of the reference genome, from the counts kept by growstep
     pub fn entropy(&self) -> Vec<(String, f32)> {
-        let mut entropies: Vec<(String, f32)> = vec![];
-        for (i, bp) in self.settings.genome.chars().enumerate() {
-            let mut gcta: Vec<f32> = vec![0.0, 0.0, 0.0, 0.0];
-            for genome in &self.genomes {
-                match genome.chars().nth(i).expect("index value not found") {
-                    'G' => gcta[0] += 1.0,
-                    'C' => gcta[1] += 1.0,
-                    'T' => gcta[2] += 1.0,
-                    'A' => gcta[3] += 1.0,
-                    _ => panic!("base pair not found")
+        self.sites.reference().bases().zip(self.sites.entropy(self.size)).map(
+            |(bp, entropy)| ((bp as char).to_string(), entropy)
+        ).collect()
+    }
+
+    // entropy summed over every site of the reference genome
+    pub fn total_entropy(&self) -> f32 {
+        self.sites.entropy(self.size).iter().sum()
+    }
+
+    // how cell i reads the messages it hears this step. this only looks at the organism as it
+    // stood at the start of the step, so cells can listen in any order or in parallel
+    fn listen(&self, i: usize, seed: [u8; 32], table: &KernelTable, occupancy: &Occupancy) -> Reading {
+        // each cell draws from its own stream of the step's seed, so the result
+        // doesn't depend on which thread handles which cell
+        let mut rng = ChaCha8Rng::from_seed(seed);
+        rng.set_stream(i as u64);
+        let threshsplit = rng.gen::<f32>();
+        let coordi = &self.coordinates[i];
+        let mut sources: Vec<(usize, f32)> = vec![];
+        match self.settings.sampling {
+            Sampling::AllPairs => {
+                // only cells within the kernel's radius can be heard, scanned in grid order
+                for (j, distance, prob) in occupancy.around(coordi, table) {
+                    let thresh = rng.gen::<f32>();
+                    if prob > thresh {
+                        sources.push((j, distance));
+                    }
+                }
+            },
+            Sampling::Budget { messages, poisson } => {
+                let budget = if poisson && messages > 0 {
+                    Poisson::new(messages as f32).unwrap().sample(&mut rng) as u32
+                } else {
+                    messages
+                };
+                for _ in 0..budget {
+                    // redraw sites that are empty so the budget is only spent on cells, giving up
+                    // on a message if the surroundings are almost entirely empty or the kernel
+                    // covers no site at all
+                    for _ in 0..MAX_SAMPLING_ATTEMPTS {
+                        let Some((site, distance)) = coordi.sample_neighbour(table, &mut rng) else { break };
+                        if let Some(j) = occupancy.resolve(&site).and_then(|site| occupancy.get(&site)) {
+                            sources.push((j, distance));
+                            break
+                        }
+                    }
                 }
             }
-            gcta = gcta.iter().map(|x| x / (self.size as f32)).collect();
-            let entropy = gcta.iter().fold(0f32, |acc, x| acc - (x * (x + 0.000000000000000001).ln()));
-            entropies.push((bp.to_string(), entropy));
         }
-        return entropies
+        let mut sasp_heard = 0;
+        let messages: Vec<Message> = sources.into_iter().map(|(j, distance)| {
+            if self.senescent[j] {
+                sasp_heard += 1;
+            }
+            // cells sharing a haplotype carry the same genome, so there is nothing to compare
+            let difference = if self.genomes[i] == self.genomes[j] {
+                0.0
+            } else {
+                self.settings.alignment.difference(self.genome(i), self.genome(j))
+            };
+            Message { genome: self.genome(j), distance, difference }
+        }).collect();
+        let age = self.settings.age_estimator.estimate(self.genome(i), &messages, self.ages[i])
+            + self.settings.senescence.sasp * sasp_heard as f32;
+        let senescent = self.senescent[i] || self.settings.senescence.triggers(age, &mut rng);
+        Reading { age, heard: messages.len() as u32, senescent, threshsplit, rng }
+    }
+
+    // every cell's reading, spread over threads if parallel and the parallel feature is on
+    fn listen_all(&self, seed: [u8; 32], table: &KernelTable, occupancy: &Occupancy, parallel: bool) -> Vec<Reading> {
+        let cells = 0..self.coordinates.len();
+        #[cfg(feature = "parallel")]
+        if parallel {
+            return cells.into_par_iter().map(|i| self.listen(i, seed, table, occupancy)).collect();
+        }
+        #[cfg(not(feature = "parallel"))]
+        let _ = parallel;
+        cells.map(|i| self.listen(i, seed, table, occupancy)).collect()
     }
 
-    // TODO implement multithreading
     pub fn growstep(self) -> Organism {
-        let mut rng = thread_rng();
+        self.step(cfg!(feature = "parallel"))
+    }
+
+    fn step(mut self, parallel: bool) -> Organism {
+        let seed: [u8; 32] = self.rng.gen();
+        let depth = self.settings.lattice.depth(self.settings.length);
+        let mut occupancy = Occupancy::new(
+            self.settings.length, depth, self.settings.boundary, self.settings.mask.as_ref(), &self.coordinates
+        );
+        let cached = self.table.get(&self.settings);
+        let table = &cached.1;
+        let readings = self.listen_all(seed, table, &occupancy, parallel);
         let mut new_coords = self.coordinates.clone();
         let mut new_age = self.ages.clone();
-        let mut new_genes: Vec<String> = self.genomes.clone();
+        let mut new_senescent = self.senescent.clone();
+        let mut new_genes: Vec<HaplotypeId> = self.genomes.clone();
+        let mut new_ids = self.ids.clone();
+        let born = self.step + 1;
+        let haplotypes = &mut self.haplotypes;
+        let sites = &mut self.sites;
+        let settings = &self.settings;
         let mut total_cells_sampled = 0;
-        for (i, coordi) in self.coordinates.iter().enumerate() {
-            let mut age = 0.0;
-            let threshsplit = rng.gen::<f32>();
-            let probsplit = 0.02 * E.powf(-1f32 * (new_age[i]));
-            let split = probsplit > threshsplit;
-            let mut countcells = 0;  // this is a counter for cells sampled
-            let mut neighbours: Vec<Location> = coordi.get_neighbours().into_iter().filter(
-                |l| (l.x < self.settings.length as i32) & (l.y < self.settings.length as i32)
+        let mut mutations = 0;
+        let silent = self.settings.silent_mutations;
+        let model = &self.settings.substitution;
+        // divisions and mutations are applied in cell order, each cell still drawing from its own stream
+        for (i, mut reading) in readings.into_iter().enumerate() {
+            let rng = &mut reading.rng;
+            let mut neighbours: Vec<Location> = self.coordinates[i].get_neighbours(&self.settings.lattice).iter().filter_map(
+                |l| occupancy.resolve(l)
             ).collect();
-            for (j, coordj) in new_coords.iter().enumerate() {
-                neighbours.retain(|x| x != coordj);
-                let prob = E.powf(-0.2 * distance_calc(coordj, coordi));
-                let thresh = rng.gen::<f32>();
-                if prob > thresh {
-                    countcells += 1;
-                    age = age + difference_rna(&new_genes[i], &new_genes[j])
-                } else {
-                    age = age + 0.0
+            let adjacent = neighbours.len();
+            neighbours.retain(|l| occupancy.is_free(l));
+            // senescent cells no longer divide
+            let probsplit = self.settings.division.probability(new_age[i], neighbours.len(), adjacent);
+            let split = (probsplit > reading.threshsplit) & !reading.senescent;
+            if split & !neighbours.is_empty() {
+                let site = neighbours.choose(rng).unwrap().clone();
+                occupancy.insert(&site, new_coords.len());
+                new_coords.push(site);
+                new_age.push(new_age[i]);
+                new_senescent.push(false);
+                new_ids.push(self.lineage.divide(new_ids[i], born));
+                // the daughter shares its parent's haplotype until either of them mutates
+                haplotypes.retain(new_genes[i]);
+                sites.add(haplotypes.get(new_genes[i]), haplotypes.map(new_genes[i]));
+                let (daughter, count) = haplotypes.mutate(new_genes[i], sites, |gene| {
+                    gene_mutation(gene, settings.growth_rate, model, silent, rng)
+                });
+                new_genes.push(daughter);
+                mutations += count;
+                let (parent, count) = haplotypes.mutate(new_genes[i], sites, |gene| {
+                    gene_mutation(gene, settings.growth_rate, model, silent, rng)
+                });
+                new_genes[i] = parent;
+                mutations += count;
+            }
+            new_age[i] = reading.age;
+            new_senescent[i] = reading.senescent;
+            let (gene, count) = haplotypes.mutate(new_genes[i], sites, |gene| {
+                let mut changes = gene_mutation(gene, settings.mutation_rate, model, silent, rng);
+                changes.extend(indel_mutation(gene, settings.insertion_rate, settings.deletion_rate, model, rng));
+                changes
+            });
+            new_genes[i] = gene;
+            mutations += count;
+            total_cells_sampled += reading.heard;
+        }
+        // dying cells free their sites, which neighbours can divide into from the next step
+        let mut alive = vec![true; new_coords.len()];
+        if self.settings.death.enabled() {
+            for (k, keep) in alive.iter_mut().enumerate() {
+                *keep = !self.settings.death.triggers(new_age[k], new_senescent[k], &mut self.rng);
+                if !*keep {
+                    self.sites.remove(self.haplotypes.get(new_genes[k]), self.haplotypes.map(new_genes[k]));
+                    self.haplotypes.release(new_genes[k]);
+                    self.lineage.die(new_ids[k], born);
                 }
-            };
-            if split & (neighbours.len() > 0) {
-                new_coords.push(
-                    neighbours.choose(&mut rng).unwrap().clone()
-                );
-                new_age.push(new_age[i].clone());
-                new_genes.push(
-                    gene_mutation(new_genes[i].clone(), self.settings.growth_rate)
-                );
-                new_genes[i] = gene_mutation(new_genes[i].clone(), self.settings.growth_rate);
             }
-            new_age[i] = age;
-            new_genes[i] = gene_mutation(new_genes[i].clone(), self.settings.mutation_rate);
-            total_cells_sampled += countcells;
+        }
+        let deaths = alive.iter().filter(|a| !**a).count() as u32;
+        if deaths > 0 {
+            new_coords = survivors(new_coords, &alive);
+            new_age = survivors(new_age, &alive);
+            new_senescent = survivors(new_senescent, &alive);
+            new_genes = survivors(new_genes, &alive);
+            new_ids = survivors(new_ids, &alive);
         }
         Organism {
             coordinates: new_coords.clone(),
             ages: new_age,
-            senescent: self.senescent,
+            senescent: new_senescent,
             genomes: new_genes,
+            haplotypes: self.haplotypes,
+            ids: new_ids,
+            lineage: self.lineage,
+            sites: self.sites,
             settings: self.settings,
             size: new_coords.len() as u32,
-            samplesize: total_cells_sampled / self.coordinates.len() as u32
+            samplesize: total_cells_sampled / self.coordinates.len().max(1) as u32,
+            step: self.step + 1,
+            mutations: mutations as u32,
+            deaths,
+            rng: self.rng,
+            table: self.table
         }
     }
 }
 
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    // small enough to run quickly in a debug build, with division, death and indels all in play
+    pub(crate) fn settings() -> Settings {
+        Settings {
+            length: 16,
+            genome: "GATTACA".repeat(5),
+            mutation_rate: 0.01,
+            growth_rate: 0.01,
+            insertion_rate: 0.002,
+            deletion_rate: 0.002,
+            division: DivisionPolicyKind::Exponential { rate: 0.3, decay: 0.01 },
+            death: Death { base_rate: 0.01, ..Death::default() },
+            ..Settings::default()
+        }
+    }
+
+    pub(crate) fn grow(mut organism: Organism, steps: u32) -> Organism {
+        for _ in 0..steps {
+            organism = organism.growstep();
+        }
+        organism
+    }
+
+    // FNV-1a over what each step reports, enough to tell two trajectories apart
+    fn fingerprint(settings: &Settings, steps: u32) -> u64 {
+        let mut organism = settings.init_organism().unwrap();
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        for _ in 0..steps {
+            organism = organism.growstep();
+            let values = [
+                organism.size, organism.samplesize, organism.mutations, organism.deaths, organism.mean_age().to_bits()
+            ];
+            for value in values {
+                hash = (hash ^ value as u64).wrapping_mul(0x0000_0100_0000_01b3);
+            }
+        }
+        hash
+    }
+
+    #[test]
+    fn same_settings_same_trajectory() {
+        let a = grow(settings().init_organism().unwrap(), 30);
+        let b = grow(settings().init_organism().unwrap(), 30);
+        assert!(a.size > 1);
+        assert!(a == b);
+    }
+
+    // the serial and parallel builds both have to land on this value, so run the tests with
+    // and without the parallel feature. it only changes when growstep is meant to change.
+    #[test]
+    fn trajectory_matches_fingerprint() {
+        assert_eq!(fingerprint(&settings(), 40), 0x9bb4_8a4c_1167_e44f);
+    }
+
+    // the same steps taken serially and spread over several threads
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn parallel_matches_serial() {
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(4).build().unwrap();
+        pool.install(|| {
+            let mut serial = settings().init_organism().unwrap();
+            let mut parallel = serial.clone();
+            for _ in 0..40 {
+                serial = serial.step(false);
+                parallel = parallel.step(true);
+                assert!(serial == parallel);
+            }
+            assert!(serial.size > 1);
+        });
+    }
+
+    #[test]
+    fn founders_must_be_distinct_sites_of_the_arena() {
+        let settings = settings();
+        let founder = |x| Founder {
+            location: Location { x, y: 0, z: 0 }, genome: settings.reference().unwrap(), age: 0.0, senescent: false
+        };
+        assert!(matches!(
+            settings.init_with_founders(vec![founder(16)]), Err(InitError::FounderOffArena(Location { x: 16, .. }))
+        ));
+        assert!(matches!(
+            settings.init_with_founders(vec![founder(3), founder(3)]), Err(InitError::DuplicateFounder(Location { x: 3, .. }))
+        ));
+        assert!(settings.init_with_founders(vec![founder(3), founder(4)]).is_ok());
+    }
+
+    #[test]
+    fn mask_must_fit_a_bounded_arena() {
+        let mut settings = settings();
+        settings.mask = Some(Mask::from_ascii(&format!("{}@", ".".repeat(30))).unwrap());
+        assert!(matches!(
+            settings.init_organism(), Err(InitError::MaskTooLarge { width: 31, height: 1, length: 16 })
+        ));
+        settings.boundary = Boundary::Open;
+        assert!(settings.init_organism().is_ok());
+    }
+
+    // far apart cells and a kernel that never falls off only cost the blocks and reach they need
+    #[test]
+    fn open_arena_stays_small() {
+        let settings = Settings { boundary: Boundary::Open, kernel: MessageKernelKind::PowerLaw { exponent: 1.0 }, ..settings() };
+        let founder = |x| Founder {
+            location: Location { x, y: 0, z: 0 }, genome: settings.reference().unwrap(), age: 0.0, senescent: false
+        };
+        let organism = grow(settings.init_with_founders(vec![founder(-50000), founder(50000)]).unwrap(), 3);
+        assert!(organism.size >= 2);
+        let never_cut_off = Settings { kernel: MessageKernelKind::PowerLaw { exponent: 0.0 }, ..settings };
+        assert!(matches!(never_cut_off.init_organism(), Err(InitError::KernelRadius(_))));
+    }
+
+    // on a torus of even length the row and column half way round are heard from once each
+    #[test]
+    fn periodic_reach_covers_the_antipodes_once() {
+        let settings = Settings {
+            length: 4,
+            boundary: Boundary::Periodic,
+            initial: InitialState::RandomFill { fraction: 1.0 },
+            kernel: MessageKernelKind::TopHat { radius: 10.0 },
+            ..settings()
+        };
+        let organism = settings.init_organism().unwrap().growstep();
+        assert_eq!(organism.samplesize, 16);
+    }
+
+    // a kernel that reaches no site, not even the cell's own, leaves budget messages unheard
+    #[test]
+    fn empty_kernel_drops_budget_messages() {
+        let settings = Settings {
+            kernel: MessageKernelKind::TopHat { radius: -1.0 },
+            sampling: Sampling::Budget { messages: 5, poisson: false },
+            ..settings()
+        };
+        let organism = grow(settings.init_organism().unwrap(), 5);
+        assert_eq!(organism.samplesize, 0);
+    }
+
+    #[test]
+    fn site_counts_follow_the_cells() {
+        let organism = grow(settings().init_organism().unwrap(), 40);
+        let mut counted = SiteCounts::new(settings().reference().unwrap());
+        for id in &organism.genomes {
+            counted.add(organism.haplotypes.get(*id), organism.haplotypes.map(*id));
+        }
+        assert!(counted == organism.sites);
+    }
+}
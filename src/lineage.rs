@@ -0,0 +1,229 @@
+use std::fmt::Write;
+use serde::{Deserialize, Serialize};
+
+// a cell's handle in the lineage, given out in order of birth and never reused
+pub type CellId = u64;
+
+// one cell's place in the division history
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CellRecord {
+    // None for founders
+    pub parent: Option<CellId>,
+    pub birth: u32,
+    pub divisions: u32,
+    pub death: Option<u32>
+}
+
+// every cell the organism has ever had, alive or dead, indexed by CellId
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct Lineage {
+    cells: Vec<CellRecord>
+}
+
+// the division history as a tree. each division splits a cell's branch in two, the parent
+// carrying on down the first child and the daughter starting the second.
+struct Node {
+    // the cell a leaf ends as, internal nodes have none
+    cell: Option<CellId>,
+    // steps since the node above, None at the root
+    length: Option<u32>,
+    children: Vec<usize>
+}
+
+impl Lineage {
+    pub fn get(&self, id: CellId) -> &CellRecord {
+        &self.cells[id as usize]
+    }
+
+    pub fn len(&self) -> usize {
+        self.cells.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+
+    fn push(&mut self, parent: Option<CellId>, step: u32) -> CellId {
+        self.cells.push(CellRecord { parent, birth: step, divisions: 0, death: None });
+        (self.cells.len() - 1) as CellId
+    }
+
+    // a cell with no parent, present from step
+    pub fn found(&mut self, step: u32) -> CellId {
+        self.push(None, step)
+    }
+
+    // parent divided at step, returning its daughter
+    pub fn divide(&mut self, parent: CellId, step: u32) -> CellId {
+        self.cells[parent as usize].divisions += 1;
+        self.push(Some(parent), step)
+    }
+
+    pub fn die(&mut self, id: CellId, step: u32) {
+        self.cells[id as usize].death = Some(step);
+    }
+
+    // built with an explicit stack rather than recursion, long-lived lineages nest very deeply
+    fn tree(&self, now: u32) -> Vec<Node> {
+        let mut daughters: Vec<Vec<CellId>> = vec![vec![]; self.cells.len()];
+        let mut founders = vec![];
+        for (id, cell) in self.cells.iter().enumerate() {
+            match cell.parent {
+                Some(parent) => daughters[parent as usize].push(id as CellId),
+                None => founders.push(id as CellId)
+            }
+        }
+        let mut nodes = vec![];
+        // several founders hang off a root of their own
+        let root = if founders.len() > 1 {
+            nodes.push(Node { cell: None, length: None, children: vec![] });
+            Some(0)
+        } else {
+            None
+        };
+        // the rest of cell's branch from its kth division on, starting at step start
+        let mut stack: Vec<(CellId, usize, u32, Option<usize>)> = founders.iter().rev().map(
+            |id| (*id, 0, self.get(*id).birth, root)
+        ).collect();
+        while let Some((id, k, start, above)) = stack.pop() {
+            let node = nodes.len();
+            // the branch runs until the cell's next division, or its death, or now
+            let (cell, end) = match daughters[id as usize].get(k) {
+                Some(daughter) => {
+                    let split = self.get(*daughter).birth;
+                    stack.push((*daughter, 0, split, Some(node)));
+                    stack.push((id, k + 1, split, Some(node)));
+                    (None, split)
+                },
+                None => (Some(id), self.get(id).death.unwrap_or(now))
+            };
+            nodes.push(Node { cell, length: Some(end - start), children: vec![] });
+            if let Some(above) = above {
+                nodes[above].children.push(node);
+            }
+        }
+        nodes
+    }
+
+    // the division history up to step now in newick format, branch lengths in steps
+    // and leaves labelled by the cells they end as
+    pub fn to_newick(&self, now: u32) -> String {
+        enum Piece { Open(usize), Comma, Close(usize) }
+        let nodes = self.tree(now);
+        let mut out = String::new();
+        let mut stack = if nodes.is_empty() { vec![] } else { vec![Piece::Open(0)] };
+        while let Some(piece) = stack.pop() {
+            let n = match piece {
+                Piece::Comma => {
+                    out.push(',');
+                    continue
+                },
+                Piece::Open(n) if !nodes[n].children.is_empty() => {
+                    out.push('(');
+                    stack.push(Piece::Close(n));
+                    for (j, child) in nodes[n].children.iter().enumerate().rev() {
+                        stack.push(Piece::Open(*child));
+                        if j > 0 {
+                            stack.push(Piece::Comma);
+                        }
+                    }
+                    continue
+                },
+                Piece::Open(n) => n,
+                Piece::Close(n) => {
+                    out.push(')');
+                    n
+                }
+            };
+            if let Some(cell) = nodes[n].cell {
+                write!(out, "cell{}", cell).unwrap();
+            }
+            if let Some(length) = nodes[n].length {
+                write!(out, ":{}", length).unwrap();
+            }
+        }
+        out.push(';');
+        out
+    }
+
+    // the same tree as to_newick as a nexml document
+    pub fn to_nexml(&self, now: u32) -> String {
+        let nodes = self.tree(now);
+        let mut parents = vec![None; nodes.len()];
+        for (n, node) in nodes.iter().enumerate() {
+            for child in &node.children {
+                parents[*child] = Some(n);
+            }
+        }
+        let mut out = String::new();
+        out.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        out.push_str(concat!(
+            "<nex:nexml version=\"0.9\" xmlns=\"http://www.nexml.org/2009\" ",
+            "xmlns:nex=\"http://www.nexml.org/2009\" xmlns:xsi=\"http://www.w3.org/2001/XMLSchema-instance\">\n"
+        ));
+        out.push_str("  <otus id=\"cells\">\n");
+        for cell in nodes.iter().filter_map(|node| node.cell) {
+            writeln!(out, "    <otu id=\"cell{0}\" label=\"cell{0}\"/>", cell).unwrap();
+        }
+        out.push_str("  </otus>\n");
+        out.push_str("  <trees id=\"lineage\" otus=\"cells\">\n");
+        out.push_str("    <tree id=\"divisions\" xsi:type=\"nex:IntTree\">\n");
+        for (n, node) in nodes.iter().enumerate() {
+            write!(out, "      <node id=\"n{}\"", n).unwrap();
+            if let Some(cell) = node.cell {
+                write!(out, " otu=\"cell{}\"", cell).unwrap();
+            }
+            if n == 0 {
+                out.push_str(" root=\"true\"");
+            }
+            out.push_str("/>\n");
+        }
+        if let Some(length) = nodes.first().and_then(|root| root.length) {
+            writeln!(out, "      <rootedge id=\"e0\" target=\"n0\" length=\"{}\"/>", length).unwrap();
+        }
+        for (n, node) in nodes.iter().enumerate() {
+            if let (Some(parent), Some(length)) = (parents[n], node.length) {
+                writeln!(out, "      <edge id=\"e{0}\" source=\"n{1}\" target=\"n{0}\" length=\"{2}\"/>", n, parent, length).unwrap();
+            }
+        }
+        out.push_str("    </tree>\n  </trees>\n</nex:nexml>\n");
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn newick_of_a_single_founder() {
+        let mut lineage = Lineage::default();
+        let founder = lineage.found(0);
+        assert_eq!(lineage.to_newick(3), "cell0:3;");
+        let daughter = lineage.divide(founder, 1);
+        lineage.divide(daughter, 2);
+        lineage.die(founder, 3);
+        assert_eq!(lineage.to_newick(4), "(cell0:2,(cell1:2,cell2:2):1):1;");
+        assert_eq!(lineage.get(founder).divisions, 1);
+    }
+
+    #[test]
+    fn founders_share_a_root() {
+        let mut lineage = Lineage::default();
+        let a = lineage.found(0);
+        lineage.found(0);
+        lineage.divide(a, 2);
+        assert_eq!(lineage.to_newick(5), "((cell0:3,cell2:3):2,cell1:5);");
+    }
+
+    #[test]
+    fn nexml_has_a_node_per_newick_node() {
+        let mut lineage = Lineage::default();
+        let founder = lineage.found(0);
+        lineage.divide(founder, 1);
+        let nexml = lineage.to_nexml(2);
+        assert_eq!(nexml.matches("<node ").count(), 3);
+        assert_eq!(nexml.matches("<edge ").count(), 2);
+        assert!(nexml.contains("<rootedge id=\"e0\" target=\"n0\" length=\"1\"/>"));
+    }
+}
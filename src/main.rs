@@ -1,6 +1,6 @@
 use yew::prelude::*;
 use yew::{Properties};
-use agesim::{Settings, Organism};
+use agesim::{Settings, Organism, Lattice, Location, Boundary};
 use wasm_logger;
 use log;
 use gloo_timers::callback::Timeout;
@@ -24,7 +24,14 @@ struct OrganismProps {
     ages: Vec<f32>
 }
 
-fn drawsim(org: Organism) {
+// 3d organisms are drawn one layer at a time, or flattened down z
+#[derive(Clone, Copy, PartialEq)]
+enum View {
+    Slice(i32),
+    Projection
+}
+
+fn drawsim(org: Organism, view: View) {
     let maxage: f64 = org.ages.clone().into_iter().reduce(f32::min).unwrap_or(0f32) as f64;
     let grad = colorgrad::CustomGradient::new()
         .html_colors(&["deeppink", "gold", "seagreen"])
@@ -48,21 +55,47 @@ fn drawsim(org: Organism) {
     canvas.set_width(width as u32);
     canvas.set_height(height as u32);
     assert_eq!(width, height);
-    let diameter = (width as f64) / (org.settings.length as f64);
+    // fit the canvas to the arena, or to the organism when it has no arena
+    let (left, top, side) = org.frame();
+    let diameter = (width as f64) / (side as f64);
+    let lattice = org.settings.lattice;
+    let cells: Vec<(Location, f32)> = match view {
+        _ if lattice.dimensions() == 2 => org.coordinates.iter().cloned().zip(org.ages.iter().copied()).collect(),
+        View::Slice(z) => org.slice(z).into_iter().map(|i| (org.coordinates[i].clone(), org.ages[i])).collect(),
+        View::Projection => org.projection().into_iter().map(|(column, age, _)| (column, age)).collect()
+    };
     context.begin_path();
-    for (i, coords) in org.coordinates.iter().enumerate() {
-        let relage = (org.ages[i] as f64) / 150f64.max(maxage);
+    for (coords, age) in cells.iter() {
+        let relage = (*age as f64) / 150f64.max(maxage);
         let clr = grad.at(relage).rgba_u8();
         context
             .set_fill_style(&JsValue::from_str( // clr.3 is the alpha
                 &format!("rgba({}, {}, {}, {})", clr.0, clr.1, clr.2, 1)
             ));
-        context // fill_rect
-            .fill_rect(
-                (coords.x as f64) * diameter, 
-                (coords.y as f64) * diameter,
-                diameter, diameter
-            );
+        let (x, y) = lattice.position(coords);
+        let centre_x = ((x - left) as f64) * diameter;
+        let centre_y = ((y - top) as f64) * diameter;
+        match lattice {
+            Lattice::Hexagonal => {
+                // pointy topped, one diameter across the flats
+                let radius = (lattice.extent() as f64) * diameter;
+                context.begin_path();
+                for k in 0..6 {
+                    let angle = f64::consts::PI / 6.0 + (k as f64) * f64::consts::PI / 3.0;
+                    context.line_to(centre_x + radius * angle.cos(), centre_y + radius * angle.sin());
+                }
+                context.close_path();
+                context.fill();
+            },
+            _ => {
+                context // fill_rect
+                    .fill_rect(
+                        centre_x - diameter / 2.0, 
+                        centre_y - diameter / 2.0,
+                        diameter, diameter
+                    );
+            }
+        }
         context.set_font("14px serif");
         // context
         //     .fill_text(
@@ -137,6 +170,13 @@ fn render_organism(orgpoint: &OrganismProps) -> Html{
     let orgprops = use_state(|| orgpoint.clone());
     let step = use_state(|| 0);
     let grow = use_state(|| false);
+    // start a 3d organism on the layer it was seeded in
+    let seeded = match orgpoint.settings.boundary {
+        Boundary::Open => 0,
+        _ => (orgpoint.settings.length / 2) as i32
+    };
+    let view = use_state(|| View::Slice(seeded));
+    let solid = orgpoint.settings.lattice.dimensions() == 3;
 
     let settings = orgprops.settings.clone();
     let age = orgprops.organism.mean_age();
@@ -176,11 +216,26 @@ fn render_organism(orgpoint: &OrganismProps) -> Html{
         }
     };
 
+    let layer = |change: i32| {
+        let view = view.clone();
+        move |_: MouseEvent| {
+            let z = match *view {
+                View::Slice(z) => z + change,
+                View::Projection => seeded
+            };
+            view.set(View::Slice(z));
+        }
+    };
+    let project = {
+        let view = view.clone();
+        move |_: MouseEvent| view.set(View::Projection)
+    };
+
 
     // render age plot
     drawplot("dataplot", ages, sizes);
     // render simulation
-    drawsim(organism);
+    drawsim(organism, *view);
     log::info!("Update: {:?}", exceed);
     html! {
     <>
@@ -194,6 +249,21 @@ fn render_organism(orgpoint: &OrganismProps) -> Html{
                 <button class="bg-blue-500 hover:bg-blue-700 text-white font-bold py-2 px-4 rounded w-4/12" onclick={toggle_start}>{ "Start Simulation" }</button>
                 }
             }
+            if solid {
+                <>
+                <div class="flex flex-row justify-center pt-2">
+                    <button class="bg-gray-500 hover:bg-gray-700 text-white py-1 px-3 rounded m-1" onclick={layer(-1)}>{ "Layer Down" }</button>
+                    <button class="bg-gray-500 hover:bg-gray-700 text-white py-1 px-3 rounded m-1" onclick={project}>{ "Projection" }</button>
+                    <button class="bg-gray-500 hover:bg-gray-700 text-white py-1 px-3 rounded m-1" onclick={layer(1)}>{ "Layer Up" }</button>
+                </div>
+                <p class="text-center text-sm">{
+                    match *view {
+                        View::Slice(z) => format!("Showing layer z = {}", z),
+                        View::Projection => "Showing the mean age down each column".to_string()
+                    }
+                }</p>
+                </>
+            }
             <p class="sm:w-8/12 w-full text-center text-lg pt-8"> <b>{ "Entropy of each Base" } </b></p>
             <p class="sm:w-8/12 w-full text-left pb-5"> { "\
                 Each cell has a simple genome represented as a string, in this case GATTACA. \
@@ -211,15 +281,9 @@ fn render_organism(orgpoint: &OrganismProps) -> Html{
 #[styled_component(AppComponent)]
 fn app() -> Html {
     wasm_logger::init(wasm_logger::Config::default());
-    let settings: Settings = Settings {
-            length: 20,
-            genome: "GATTACA".to_string(),
-            mutation_rate: 0.00016,
-            growth_rate: 0.01,
-            seed: 1234
-        };
+    let settings: Settings = Settings::default();
 
-    let organism: Organism = settings.init_organism();
+    let organism: Organism = settings.init_organism().expect("default settings have a valid genome");
     let orgprops = OrganismProps {
         settings: settings,
         organism: organism,
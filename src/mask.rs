@@ -0,0 +1,196 @@
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+use crate::Location;
+
+// the shape of a tissue: which sites of the arena cells may occupy, laid over it from the
+// origin, and optionally the sites the first cells sit on. with a cubic lattice every
+// layer takes the same shape.
+//
+// as text each line is a row of sites along x, rows running along y:
+//   '.' or ' '  outside the tissue
+//   '#'         tissue
+//   '@'         tissue with a seed cell on it
+// as a png, bright opaque pixels are tissue and red ones are seeds.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(into = "Vec<String>", try_from = "Vec<String>")]
+pub struct Mask {
+    width: usize,
+    height: usize,
+    allowed: Vec<bool>,
+    seeds: Vec<Location>
+}
+
+#[derive(Debug)]
+pub enum MaskError {
+    Io(io::Error),
+    #[cfg(feature = "png")]
+    Png(png::DecodingError),
+    #[cfg(not(feature = "png"))]
+    PngUnsupported,
+    Symbol(char),
+    Empty
+}
+
+impl fmt::Display for MaskError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MaskError::Io(e) => write!(f, "mask io error: {}", e),
+            #[cfg(feature = "png")]
+            MaskError::Png(e) => write!(f, "invalid png mask: {}", e),
+            #[cfg(not(feature = "png"))]
+            MaskError::PngUnsupported => write!(f, "png masks need agesim built with the png feature"),
+            MaskError::Symbol(c) => write!(f, "'{}' is not one of '.', ' ', '#' or '@' in a mask", c),
+            MaskError::Empty => write!(f, "mask has no tissue sites")
+        }
+    }
+}
+
+impl Error for MaskError {}
+
+impl From<io::Error> for MaskError {
+    fn from(e: io::Error) -> Self { MaskError::Io(e) }
+}
+
+#[cfg(feature = "png")]
+impl From<png::DecodingError> for MaskError {
+    fn from(e: png::DecodingError) -> Self { MaskError::Png(e) }
+}
+
+impl Mask {
+    // build from a row-major grid of sites, each tissue or not and seeded or not
+    fn from_sites(width: usize, height: usize, sites: impl Iterator<Item = (bool, bool)>) -> Result<Mask, MaskError> {
+        let mut allowed = Vec::with_capacity(width * height);
+        let mut seeds = vec![];
+        for (k, (tissue, seed)) in sites.enumerate() {
+            allowed.push(tissue | seed);
+            if seed {
+                seeds.push(Location { x: (k % width) as i32, y: (k / width) as i32, z: 0 });
+            }
+        }
+        if !allowed.iter().any(|a| *a) {
+            return Err(MaskError::Empty)
+        }
+        Ok(Mask { width, height, allowed, seeds })
+    }
+
+    pub fn from_ascii(text: &str) -> Result<Mask, MaskError> {
+        let rows: Vec<&str> = text.lines().collect();
+        let width = rows.iter().map(|row| row.chars().count()).max().unwrap_or(0);
+        let mut sites = Vec::with_capacity(width * rows.len());
+        for row in &rows {
+            for c in row.chars() {
+                sites.push(match c {
+                    '.' | ' ' => (false, false),
+                    '#' => (true, false),
+                    '@' => (true, true),
+                    _ => return Err(MaskError::Symbol(c))
+                });
+            }
+            // short rows are outside the tissue past their end
+            sites.extend((row.chars().count()..width).map(|_| (false, false)));
+        }
+        Mask::from_sites(width, rows.len(), sites.into_iter())
+    }
+
+    #[cfg(feature = "png")]
+    pub fn from_png(bytes: &[u8]) -> Result<Mask, MaskError> {
+        let mut decoder = png::Decoder::new(bytes);
+        // expand palettes and low bit depths to 8 bit channels
+        decoder.set_transformations(png::Transformations::normalize_to_color8());
+        let mut reader = decoder.read_info()?;
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        let frame = reader.next_frame(&mut buffer)?;
+        let channels = frame.color_type.samples();
+        let pixels = buffer[..frame.buffer_size()].chunks(channels).map(|p| {
+            let (r, g, b, a) = match p {
+                [v] => (*v, *v, *v, 255),
+                [v, a] => (*v, *v, *v, *a),
+                [r, g, b] => (*r, *g, *b, 255),
+                [r, g, b, a, ..] => (*r, *g, *b, *a),
+                [] => (0, 0, 0, 0)
+            };
+            let opaque = a >= 128;
+            let seed = opaque & (r >= 128) & (g < 128) & (b < 128);
+            let tissue = opaque & ((r as u32 + g as u32 + b as u32) >= 3 * 128);
+            (tissue, seed)
+        });
+        Mask::from_sites(frame.width as usize, frame.height as usize, pixels)
+    }
+
+    // read a mask file, as a png if it has a .png extension and text otherwise
+    pub fn load(path: &Path) -> Result<Mask, MaskError> {
+        if path.extension().is_some_and(|e| e == "png") {
+            #[cfg(feature = "png")]
+            return Mask::from_png(&fs::read(path)?);
+            #[cfg(not(feature = "png"))]
+            return Err(MaskError::PngUnsupported);
+        }
+        Mask::from_ascii(&fs::read_to_string(path)?)
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn allows(&self, location: &Location) -> bool {
+        let inside = (location.x >= 0) & (location.y >= 0)
+            & ((location.x as usize) < self.width) & ((location.y as usize) < self.height);
+        inside && self.allowed[location.y as usize * self.width + location.x as usize]
+    }
+
+    // number of tissue sites in one layer
+    pub fn area(&self) -> usize {
+        self.allowed.iter().filter(|a| **a).count()
+    }
+
+    // number of tissue sites in one layer of a length x length arena
+    pub fn area_within(&self, length: usize) -> usize {
+        (0..self.height.min(length)).map(
+            |y| self.allowed[y * self.width..y * self.width + self.width.min(length)].iter().filter(|a| **a).count()
+        ).sum()
+    }
+
+    // seed sites in row order, on layer 0
+    pub fn seeds(&self) -> &[Location] {
+        &self.seeds
+    }
+
+    // the tissue site closest to location, for when a cell has to start somewhere
+    pub fn nearest(&self, location: &Location) -> Location {
+        let sites = (0..self.allowed.len()).filter(|k| self.allowed[*k]).map(
+            |k| Location { x: (k % self.width) as i32, y: (k / self.width) as i32, z: location.z }
+        );
+        sites.min_by_key(|site| (site.x - location.x).pow(2) + (site.y - location.y).pow(2)).unwrap()
+    }
+
+    fn rows(&self) -> Vec<String> {
+        let mut symbols: Vec<char> = self.allowed.iter().map(|a| if *a { '#' } else { '.' }).collect();
+        for seed in &self.seeds {
+            symbols[seed.y as usize * self.width + seed.x as usize] = '@';
+        }
+        symbols.chunks(self.width.max(1)).map(|row| row.iter().collect()).collect()
+    }
+}
+
+// kept in settings and snapshots in the text form
+impl From<Mask> for Vec<String> {
+    fn from(mask: Mask) -> Self {
+        mask.rows()
+    }
+}
+
+impl TryFrom<Vec<String>> for Mask {
+    type Error = MaskError;
+
+    fn try_from(rows: Vec<String>) -> Result<Self, Self::Error> {
+        Mask::from_ascii(&rows.join("\n"))
+    }
+}
@@ -0,0 +1,189 @@
+use std::borrow::Cow;
+use rand::prelude::*;
+use rand_distr::Geometric;
+use serde::{Deserialize, Serialize};
+use crate::Genome;
+
+pub const BASES: [u8; 4] = [b'G', b'C', b'A', b'T'];
+
+// which base a mutation turns into, frequencies are given in BASES order (G, C, A, T)
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub enum SubstitutionModel {
+    // Jukes-Cantor: every base equally likely
+    #[default]
+    JC69,
+    // Kimura 2-parameter: transitions (A<->G, C<->T) are kappa times as likely as transversions
+    K80 { kappa: f32 },
+    // Hasegawa-Kishino-Yano: K80 with each target weighted by its equilibrium frequency
+    HKY85 { kappa: f32, frequencies: [f32; 4] }
+}
+
+fn is_transition(a: u8, b: u8) -> bool {
+    matches!((a, b), (b'A', b'G') | (b'G', b'A') | (b'C', b'T') | (b'T', b'C'))
+}
+
+impl SubstitutionModel {
+    // equilibrium base frequencies, which inserted bases are drawn from
+    pub fn frequencies(&self) -> [f32; 4] {
+        match self {
+            SubstitutionModel::HKY85 { frequencies, .. } => *frequencies,
+            _ => [0.25; 4]
+        }
+    }
+
+    // relative rate of base turning into target, the row of the rate matrix without its diagonal
+    fn weight(&self, base: u8, target: usize) -> f32 {
+        let transition = is_transition(base, BASES[target]);
+        match self {
+            SubstitutionModel::JC69 => 1.0,
+            SubstitutionModel::K80 { kappa } => if transition { *kappa } else { 1.0 },
+            SubstitutionModel::HKY85 { kappa, frequencies } => {
+                frequencies[target] * if transition { *kappa } else { 1.0 }
+            }
+        }
+    }
+
+    // pick the replacement base, excluding the current one unless silent substitutions are allowed.
+    // a silent draw keeps the base with the weight of a transversion to it.
+    pub fn substitute<R: Rng + ?Sized>(&self, base: u8, silent: bool, rng: &mut R) -> u8 {
+        let weights: Vec<f32> = (0..BASES.len()).map(|j| {
+            if BASES[j] != base {
+                self.weight(base, j)
+            } else if silent {
+                match self {
+                    SubstitutionModel::HKY85 { frequencies, .. } => frequencies[j],
+                    _ => 1.0
+                }
+            } else {
+                0.0
+            }
+        }).collect();
+        BASES[weighted_choice(&weights, rng)]
+    }
+}
+
+fn weighted_choice<R: Rng + ?Sized>(weights: &[f32], rng: &mut R) -> usize {
+    let mut draw = rng.gen::<f32>() * weights.iter().sum::<f32>();
+    for (j, w) in weights.iter().enumerate() {
+        if draw < *w {
+            return j
+        }
+        draw -= w;
+    }
+    // rounding left draw past the last weight
+    weights.iter().rposition(|w| *w > 0.0).unwrap_or(0)
+}
+
+// a single change made to a genome, positions index the genome after the change.
+// a substitution has from == to only when silent mutations are allowed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Mutation {
+    Substitution { position: usize, from: char, to: char },
+    Insertion { position: usize, base: char },
+    Deletion { position: usize, base: char }
+}
+
+// mutate each base independently with probability rate and report what changed.
+// the gap to the next mutated site is geometric, so the cost scales with the
+// number of mutations rather than the genome length. a borrowed genome is only
+// copied once a mutation actually lands on it.
+pub fn gene_mutation<R: Rng + ?Sized>(
+    gene: &mut Cow<Genome>, rate: f32, model: &SubstitutionModel, silent: bool, rng: &mut R
+) -> Vec<Mutation> {
+    let mut mutations = vec![];
+    if rate <= 0.0 || gene.is_empty() {
+        return mutations
+    }
+    let gaps = Geometric::new(rate.min(1.0) as f64).unwrap();
+    let mut position = gaps.sample(rng) as usize;
+    while position < gene.len() {
+        let from = gene.base(position);
+        let to = model.substitute(from, silent, rng);
+        gene.to_mut().set_base(position, to);
+        mutations.push(Mutation::Substitution { position, from: from as char, to: to as char });
+        position = position.saturating_add(1 + gaps.sample(rng) as usize);
+    }
+    mutations
+}
+
+// delete each base with probability deletion_rate or insert a new one after it with
+// probability insertion_rate, inserted bases follow the model's equilibrium frequencies
+pub fn indel_mutation<R: Rng + ?Sized>(
+    gene: &mut Cow<Genome>, insertion_rate: f32, deletion_rate: f32, model: &SubstitutionModel, rng: &mut R
+) -> Vec<Mutation> {
+    let mut mutations = vec![];
+    let rate = (insertion_rate + deletion_rate).min(1.0);
+    if rate <= 0.0 || gene.is_empty() {
+        return mutations
+    }
+    let gaps = Geometric::new(rate as f64).unwrap();
+    let mut indelled = Genome::default();
+    let mut position = gaps.sample(rng) as usize;
+    let mut copied = 0;
+    while position < gene.len() {
+        (copied..position).for_each(|k| indelled.push_code(gene.code(k)));
+        let code = gene.code(position);
+        if rng.gen::<f32>() * (insertion_rate + deletion_rate) < deletion_rate {
+            mutations.push(Mutation::Deletion { position: indelled.len(), base: BASES[code as usize] as char });
+        } else {
+            indelled.push_code(code);
+            let inserted = weighted_choice(&model.frequencies(), rng);
+            mutations.push(Mutation::Insertion { position: indelled.len(), base: BASES[inserted] as char });
+            indelled.push_code(inserted as u8);
+        }
+        copied = position + 1;
+        position = position.saturating_add(1 + gaps.sample(rng) as usize);
+    }
+    if !mutations.is_empty() {
+        (copied..gene.len()).for_each(|k| indelled.push_code(gene.code(k)));
+        *gene = Cow::Owned(indelled);
+    }
+    mutations
+}
+
+#[cfg(test)]
+mod tests {
+    use rand_chacha::ChaCha8Rng;
+    use super::*;
+
+    #[test]
+    fn substitutions_change_the_base_unless_silent() {
+        let mut rng = ChaCha8Rng::seed_from_u64(1);
+        let models = [
+            SubstitutionModel::JC69,
+            SubstitutionModel::K80 { kappa: 4.0 },
+            SubstitutionModel::HKY85 { kappa: 2.0, frequencies: [0.1, 0.2, 0.3, 0.4] }
+        ];
+        for model in &models {
+            for base in BASES {
+                assert!((0..1000).all(|_| model.substitute(base, false, &mut rng) != base));
+            }
+        }
+        let mut gene = Cow::Owned(Genome::random(500, &mut rng));
+        let mutations = gene_mutation(&mut gene, 1.0, &SubstitutionModel::JC69, false, &mut rng);
+        assert_eq!(mutations.len(), 500);
+        assert!(mutations.iter().all(|m| matches!(m, Mutation::Substitution { from, to, .. } if from != to)));
+    }
+
+    // 100000 sites at rate 0.01 expect 1000 mutations, give or take about 32
+    #[test]
+    fn mutations_follow_the_rate() {
+        let mut rng = ChaCha8Rng::seed_from_u64(2);
+        let reference = Genome::random(10000, &mut rng);
+        let mutations: usize = (0..10).map(|_| {
+            gene_mutation(&mut Cow::Borrowed(&reference), 0.01, &SubstitutionModel::JC69, false, &mut rng).len()
+        }).sum();
+        assert!((900..1100).contains(&mutations), "{} mutations", mutations);
+    }
+
+    // from A, K80 weighs G by kappa and C and T by 1 each, so transitions outnumber
+    // transversions kappa / 2 to 1
+    #[test]
+    fn k80_transitions_follow_kappa() {
+        let mut rng = ChaCha8Rng::seed_from_u64(3);
+        let model = SubstitutionModel::K80 { kappa: 4.0 };
+        let transitions = (0..30000).filter(|_| is_transition(b'A', model.substitute(b'A', false, &mut rng))).count();
+        let ratio = transitions as f32 / (30000 - transitions) as f32;
+        assert!((1.9..2.1).contains(&ratio), "ratio {}", ratio);
+    }
+}
@@ -0,0 +1,147 @@
+use std::collections::HashMap;
+use crate::{Boundary, KernelTable, Location, Mask};
+
+const EMPTY: u32 = u32::MAX;
+
+// side of the blocks an open arena is stored in
+const CHUNK: usize = 16;
+
+type Key = (i32, i32, i32);
+
+// which cell, if any, sits at each site. a bounded arena is stored as a single dense
+// length x length block, depth layers deep in 3d. an open one has no edges to size a block
+// by, so it is split into CHUNK x CHUNK blocks, CHUNK layers deep in 3d, each made when the
+// first cell lands in it. sites outside a mask's tissue are treated as past the edge.
+pub struct Occupancy<'a> {
+    length: usize,
+    depth: usize,
+    boundary: Boundary,
+    mask: Option<&'a Mask>,
+    // sites of a block along x and y, and its layers
+    side: usize,
+    layers: usize,
+    // the block of a bounded arena, and those of an open one
+    cells: Vec<u32>,
+    blocks: HashMap<Key, Vec<u32>>
+}
+
+impl<'a> Occupancy<'a> {
+    pub fn new(
+        length: usize, depth: usize, boundary: Boundary, mask: Option<&'a Mask>, coordinates: &[Location]
+    ) -> Occupancy<'a> {
+        let (side, layers) = match boundary {
+            Boundary::Open => (CHUNK, if depth > 1 { CHUNK } else { 1 }),
+            _ => (length.max(1), depth.max(1))
+        };
+        let sites = if boundary != Boundary::Open { side * side * layers } else { 0 };
+        let mut occupancy = Occupancy {
+            length, depth, boundary, mask, side, layers, cells: vec![EMPTY; sites], blocks: HashMap::new()
+        };
+        for (i, location) in coordinates.iter().enumerate() {
+            occupancy.insert(location, i);
+        }
+        occupancy
+    }
+
+    // which block a coordinate already resolved under the boundary falls in along an axis
+    // with blocks size sites long, and where in that block
+    fn split(v: i32, size: usize) -> (i32, usize) {
+        (v.div_euclid(size as i32), v.rem_euclid(size as i32) as usize)
+    }
+
+    fn block(&self, key: &Key) -> Option<&[u32]> {
+        if self.boundary == Boundary::Open { self.blocks.get(key).map(|block| &block[..]) } else { Some(&self.cells) }
+    }
+
+    // the block holding a site of the arena and the site's index in it, None off the arena
+    fn locate(&self, location: &Location) -> Option<(Key, usize)> {
+        if self.boundary.resolve(location, self.length, self.depth).as_ref() != Some(location) {
+            return None
+        }
+        let (bx, x) = Occupancy::split(location.x, self.side);
+        let (by, y) = Occupancy::split(location.y, self.side);
+        let (bz, z) = Occupancy::split(location.z, self.layers);
+        Some(((bx, by, bz), (z * self.side + y) * self.side + x))
+    }
+
+    // the site a location refers to under the boundary, None past a hard wall or off the mask
+    pub fn resolve(&self, location: &Location) -> Option<Location> {
+        self.boundary.resolve(location, self.length, self.depth).filter(
+            |site| self.mask.is_none_or(|mask| mask.allows(site))
+        )
+    }
+
+    pub fn get(&self, location: &Location) -> Option<usize> {
+        let (key, k) = self.locate(location)?;
+        let cell = self.block(&key)?[k];
+        if cell != EMPTY { Some(cell as usize) } else { None }
+    }
+
+    pub fn is_free(&self, location: &Location) -> bool {
+        self.resolve(location).is_some_and(|site| self.get(&site).is_none())
+    }
+
+    // the block and position in it of each of centre + offset for offset in -reach..=reach
+    // along one axis of length sites, None past a hard wall
+    fn axis(&self, centre: i32, reach: i32, length: usize, size: usize) -> Vec<Option<(i32, usize)>> {
+        (-reach..=reach).map(
+            |offset| self.boundary.axis(centre + offset, length).map(|v| Occupancy::split(v, size))
+        ).collect()
+    }
+
+    // the blocks an axis of around reaches, as the first one and how many there are
+    fn span(axis: &[Option<(i32, usize)>]) -> (i32, usize) {
+        let blocks = || axis.iter().flatten().map(|(block, _)| *block);
+        match (blocks().min(), blocks().max()) {
+            (Some(low), Some(high)) => (low, (high - low + 1) as usize),
+            _ => (0, 0)
+        }
+    }
+
+    // occupied sites among the offsets of table within the kernel's radius of centre, in the
+    // table's order, with their distance and probability. each axis is resolved under the
+    // boundary and the blocks it spans looked up once, and turned into what each offset adds
+    // to the index of its block and of its site in the block, so the sites are read straight
+    // off the blocks. sites off a mask never hold a cell.
+    pub fn around<'b>(
+        &'b self, centre: &Location, table: &'b KernelTable
+    ) -> impl Iterator<Item = (usize, f32, f32)> + 'b {
+        let (reach, depth) = (table.reach(), table.depth());
+        let xs = self.axis(centre.x, reach, self.length, self.side);
+        let ys = self.axis(centre.y, reach, self.length, self.side);
+        let zs = self.axis(centre.z, depth, self.depth, self.layers);
+        let ((x0, nx), (y0, ny), (z0, nz)) = (Occupancy::span(&xs), Occupancy::span(&ys), Occupancy::span(&zs));
+        let mut blocks: Vec<Option<&[u32]>> = Vec::with_capacity(nx * ny * nz);
+        for bz in 0..nz as i32 {
+            for by in 0..ny as i32 {
+                for bx in 0..nx as i32 {
+                    blocks.push(self.block(&(x0 + bx, y0 + by, z0 + bz)));
+                }
+            }
+        }
+        let strides = |axis: Vec<Option<(i32, usize)>>, low: i32, blocks: usize, sites: usize| -> Vec<Option<(usize, usize)>> {
+            axis.into_iter().map(|v| v.map(|(block, site)| ((block - low) as usize * blocks, site * sites))).collect()
+        };
+        let xs = strides(xs, x0, 1, 1);
+        let ys = strides(ys, y0, nx, self.side);
+        let zs = strides(zs, z0, nx * ny, self.side * self.side);
+        table.within().iter().filter_map(move |&(dx, dy, dz, distance, prob)| {
+            let (bx, x) = xs[(dx + reach) as usize]?;
+            let (by, y) = ys[(dy + reach) as usize]?;
+            let (bz, z) = zs[(dz + depth) as usize]?;
+            let cell = blocks[bz + by + bx]?[z + y + x];
+            if cell != EMPTY { Some((cell as usize, distance, prob)) } else { None }
+        })
+    }
+
+    // record cell at a site of the arena
+    pub fn insert(&mut self, location: &Location, cell: usize) {
+        let Some((key, k)) = self.locate(location) else { return };
+        if self.boundary == Boundary::Open {
+            let sites = self.side * self.side * self.layers;
+            self.blocks.entry(key).or_insert_with(|| vec![EMPTY; sites])[k] = cell as u32;
+        } else {
+            self.cells[k] = cell as u32;
+        }
+    }
+}
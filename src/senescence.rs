@@ -0,0 +1,23 @@
+use rand::prelude::*;
+use serde::{Deserialize, Serialize};
+
+// when cells stop dividing and what they signal once they have
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Senescence {
+    // age readout above which a cell becomes senescent
+    pub age_threshold: Option<f32>,
+    // per-step probability of becoming senescent regardless of age
+    pub rate: f32,
+    // added to a cell's age readout for each message it hears from a senescent cell
+    pub sasp: f32
+}
+
+impl Senescence {
+    pub fn triggers<R: Rng + ?Sized>(&self, age: f32, rng: &mut R) -> bool {
+        if self.age_threshold.is_some_and(|threshold| age > threshold) {
+            return true
+        }
+        (self.rate > 0.0) && (rng.gen::<f32>() < self.rate)
+    }
+}
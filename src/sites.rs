@@ -0,0 +1,226 @@
+use serde::{Deserialize, Serialize};
+use crate::{Genome, Mutation, Settings};
+
+// state of a reference site a genome has lost
+const LOST: usize = 4;
+
+// index of a base aligned to a reference site in the counts below
+fn site_state(base: u8) -> usize {
+    match base {
+        b'G' => 0,
+        b'C' => 1,
+        b'T' => 2,
+        b'A' => 3,
+        _ => panic!("base pair not found")
+    }
+}
+
+// positions start..start + len of a genome lined up with sites site..site + len of the reference
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+struct Run {
+    start: usize,
+    site: usize,
+    len: usize
+}
+
+// which reference site each position of a genome descends from, as runs of consecutive
+// positions. positions in no run are inserted bases and sites in no run have been lost.
+// substitutions leave it as it is and each indel only shifts the runs after it, so a
+// lineage never has to be aligned back to the reference once it is under way.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct SiteMap {
+    runs: Vec<Run>
+}
+
+impl SiteMap {
+    // a genome lined up base for base with a reference of the same length
+    pub fn identity(len: usize) -> SiteMap {
+        let runs = if len > 0 { vec![Run { start: 0, site: 0, len }] } else { vec![] };
+        SiteMap { runs }
+    }
+
+    // from the genome position aligned against each reference site, if any
+    fn from_aligned(positions: &[Option<usize>]) -> SiteMap {
+        let mut runs: Vec<Run> = vec![];
+        for (site, position) in positions.iter().enumerate() {
+            let Some(position) = *position else { continue };
+            match runs.last_mut() {
+                Some(run) if (run.start + run.len == position) & (run.site + run.len == site) => run.len += 1,
+                _ => runs.push(Run { start: position, site, len: 1 })
+            }
+        }
+        SiteMap { runs }
+    }
+
+    // index of the first run ending past position
+    fn find(&self, position: usize) -> usize {
+        self.runs.partition_point(|run| run.start + run.len <= position)
+    }
+
+    // the reference site position descends from, None for an inserted base
+    pub fn site(&self, position: usize) -> Option<usize> {
+        self.runs.get(self.find(position)).filter(|run| run.start <= position).map(
+            |run| run.site + position - run.start
+        )
+    }
+
+    // make position the start of a run if it lies inside one
+    fn split(&mut self, position: usize) {
+        let k = self.find(position);
+        if let Some(run) = self.runs.get(k).copied().filter(|run| run.start < position) {
+            let offset = position - run.start;
+            self.runs[k].len = offset;
+            self.runs.insert(k + 1, Run { start: position, site: run.site + offset, len: run.len - offset });
+        }
+    }
+
+    // a base was inserted at position
+    fn insert(&mut self, position: usize) {
+        self.split(position);
+        let k = self.find(position);
+        self.runs[k..].iter_mut().for_each(|run| run.start += 1);
+    }
+
+    // the base at position was deleted, returning the site it stood for
+    fn delete(&mut self, position: usize) -> Option<usize> {
+        self.split(position);
+        self.split(position + 1);
+        let k = self.find(position);
+        let site = self.runs.get(k).filter(|run| run.start == position).map(|run| run.site);
+        if site.is_some() {
+            self.runs.remove(k);
+        }
+        self.runs[k..].iter_mut().for_each(|run| run.start -= 1);
+        site
+    }
+}
+
+// counts of G, C, T, A and lost bases at every site of the reference genome over all cells,
+// kept up to date as cells divide, mutate and die so entropy never has to revisit the cells
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct SiteCounts {
+    reference: Genome,
+    counts: Vec<[u32; 5]>
+}
+
+impl SiteCounts {
+    pub fn new(reference: Genome) -> SiteCounts {
+        SiteCounts { counts: vec![[0; 5]; reference.len()], reference }
+    }
+
+    pub fn reference(&self) -> &Genome {
+        &self.reference
+    }
+
+    // how a genome the organism starts with lines up with the reference: base for base if it
+    // is as long, otherwise through the settings' alignment
+    pub fn map(&self, genome: &Genome, settings: &Settings) -> SiteMap {
+        if genome.len() == self.reference.len() {
+            SiteMap::identity(genome.len())
+        } else {
+            SiteMap::from_aligned(&settings.alignment.align(&self.reference, genome))
+        }
+    }
+
+    // the state of every reference site in genome lined up by map
+    fn states(&self, genome: &Genome, map: &SiteMap) -> Vec<usize> {
+        let mut states = vec![LOST; self.reference.len()];
+        for run in &map.runs {
+            for k in 0..run.len {
+                states[run.site + k] = site_state(genome.base(run.start + k));
+            }
+        }
+        states
+    }
+
+    // one more cell carries genome
+    pub fn add(&mut self, genome: &Genome, map: &SiteMap) {
+        for (site, state) in self.states(genome, map).into_iter().enumerate() {
+            self.counts[site][state] += 1;
+        }
+    }
+
+    // one fewer cell carries genome
+    pub fn remove(&mut self, genome: &Genome, map: &SiteMap) {
+        for (site, state) in self.states(genome, map).into_iter().enumerate() {
+            self.counts[site][state] -= 1;
+        }
+    }
+
+    // a cell's genome, lined up by map, went through mutations in order. only the sites
+    // they hit are touched, and map follows the indels along.
+    pub fn mutate(&mut self, map: &mut SiteMap, mutations: &[Mutation]) {
+        for mutation in mutations {
+            match *mutation {
+                Mutation::Substitution { position, from, to } => if let Some(site) = map.site(position) {
+                    self.counts[site][site_state(from as u8)] -= 1;
+                    self.counts[site][site_state(to as u8)] += 1;
+                },
+                Mutation::Deletion { position, base } => if let Some(site) = map.delete(position) {
+                    self.counts[site][site_state(base as u8)] -= 1;
+                    self.counts[site][LOST] += 1;
+                },
+                Mutation::Insertion { position, .. } => map.insert(position)
+            }
+        }
+    }
+
+    // entropy of each reference site when the counts are over this many cells
+    pub fn entropy(&self, cells: u32) -> Vec<f32> {
+        self.counts.iter().map(|counts| {
+            counts.iter().map(|x| *x as f32 / (cells.max(1) as f32)).fold(
+                0f32, |acc, x| acc - (x * (x + 0.000000000000000001).ln())
+            )
+        }).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the site of every position of a genome len bases long
+    fn sites(map: &SiteMap, len: usize) -> Vec<Option<usize>> {
+        (0..len).map(|position| map.site(position)).collect()
+    }
+
+    #[test]
+    fn insertions_shift_the_sites_after_them() {
+        let mut start = SiteMap::identity(4);
+        start.insert(0);
+        assert_eq!(sites(&start, 5), [None, Some(0), Some(1), Some(2), Some(3)]);
+        let mut middle = SiteMap::identity(4);
+        middle.insert(2);
+        assert_eq!(sites(&middle, 5), [Some(0), Some(1), None, Some(2), Some(3)]);
+        let mut end = SiteMap::identity(4);
+        end.insert(4);
+        assert_eq!(sites(&end, 5), [Some(0), Some(1), Some(2), Some(3), None]);
+    }
+
+    #[test]
+    fn deletions_lose_the_site_they_hit() {
+        let mut start = SiteMap::identity(4);
+        assert_eq!(start.delete(0), Some(0));
+        assert_eq!(sites(&start, 3), [Some(1), Some(2), Some(3)]);
+        let mut middle = SiteMap::identity(4);
+        assert_eq!(middle.delete(2), Some(2));
+        assert_eq!(sites(&middle, 3), [Some(0), Some(1), Some(3)]);
+        let mut end = SiteMap::identity(4);
+        assert_eq!(end.delete(3), Some(3));
+        assert_eq!(sites(&end, 3), [Some(0), Some(1), Some(2)]);
+    }
+
+    #[test]
+    fn deleting_an_inserted_base_loses_no_site() {
+        let mut map = SiteMap::identity(4);
+        map.insert(2);
+        assert_eq!(map.delete(2), None);
+        assert_eq!(sites(&map, 4), sites(&SiteMap::identity(4), 4));
+        // and again where one run ends and the next starts
+        assert_eq!(map.delete(2), Some(2));
+        map.insert(2);
+        assert_eq!(sites(&map, 4), [Some(0), Some(1), None, Some(3)]);
+        assert_eq!(map.delete(2), None);
+        assert_eq!(sites(&map, 3), [Some(0), Some(1), Some(3)]);
+    }
+}
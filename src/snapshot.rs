@@ -0,0 +1,127 @@
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+use crate::Organism;
+
+// bump when a release changes the serialized layout of Organism
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+// O is &Organism when saving and Organism when loading
+#[derive(Serialize, Deserialize)]
+struct Snapshot<O> {
+    version: u32,
+    organism: O
+}
+
+// read on its own first so an old snapshot is reported as such rather than as a parse error
+#[derive(Deserialize)]
+struct Header {
+    version: u32
+}
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(io::Error),
+    Json(serde_json::Error),
+    Binary(bincode::Error),
+    Version(u32)
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SnapshotError::Io(e) => write!(f, "snapshot io error: {}", e),
+            SnapshotError::Json(e) => write!(f, "invalid json snapshot: {}", e),
+            SnapshotError::Binary(e) => write!(f, "invalid binary snapshot: {}", e),
+            SnapshotError::Version(v) => write!(
+                f, "snapshot version {} is not supported (expected {})", v, SNAPSHOT_VERSION
+            )
+        }
+    }
+}
+
+impl Error for SnapshotError {}
+
+impl From<io::Error> for SnapshotError {
+    fn from(e: io::Error) -> Self { SnapshotError::Io(e) }
+}
+
+impl From<serde_json::Error> for SnapshotError {
+    fn from(e: serde_json::Error) -> Self { SnapshotError::Json(e) }
+}
+
+impl From<bincode::Error> for SnapshotError {
+    fn from(e: bincode::Error) -> Self { SnapshotError::Binary(e) }
+}
+
+fn check_version(version: u32) -> Result<(), SnapshotError> {
+    if version == SNAPSHOT_VERSION { Ok(()) } else { Err(SnapshotError::Version(version)) }
+}
+
+// snapshots hold the full state including the rng, so a restored organism
+// continues growstep exactly where the saved one left off
+impl Organism {
+    pub fn to_json(&self) -> Result<String, SnapshotError> {
+        Ok(serde_json::to_string(&Snapshot { version: SNAPSHOT_VERSION, organism: self })?)
+    }
+
+    pub fn from_json(json: &str) -> Result<Organism, SnapshotError> {
+        check_version(serde_json::from_str::<Header>(json)?.version)?;
+        let snapshot: Snapshot<Organism> = serde_json::from_str(json)?;
+        Ok(snapshot.organism)
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, SnapshotError> {
+        Ok(bincode::serialize(&Snapshot { version: SNAPSHOT_VERSION, organism: self })?)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Organism, SnapshotError> {
+        // the version is the leading field so it can be read without the rest
+        check_version(bincode::deserialize::<u32>(bytes)?)?;
+        let snapshot: Snapshot<Organism> = bincode::deserialize(bytes)?;
+        Ok(snapshot.organism)
+    }
+
+    // files ending in .json are written as json, anything else as binary
+    pub fn save(&self, path: &Path) -> Result<(), SnapshotError> {
+        let bytes = if is_json(path) { self.to_json()?.into_bytes() } else { self.to_bytes()? };
+        Ok(fs::write(path, bytes)?)
+    }
+
+    pub fn load(path: &Path) -> Result<Organism, SnapshotError> {
+        if is_json(path) {
+            Organism::from_json(&fs::read_to_string(path)?)
+        } else {
+            Organism::from_bytes(&fs::read(path)?)
+        }
+    }
+}
+
+fn is_json(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("json"))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::{grow, settings};
+    use crate::Organism;
+
+    #[test]
+    fn json_resume_continues_identically() {
+        let halfway = grow(settings().init_organism().unwrap(), 20);
+        let resumed = Organism::from_json(&halfway.to_json().unwrap()).unwrap();
+        assert!(resumed == halfway);
+        assert!(grow(resumed, 20) == grow(halfway, 20));
+    }
+
+    #[test]
+    fn binary_resume_continues_identically() {
+        let halfway = grow(settings().init_organism().unwrap(), 20);
+        let resumed = Organism::from_bytes(&halfway.to_bytes().unwrap()).unwrap();
+        assert!(resumed == halfway);
+        assert!(grow(resumed, 20) == grow(halfway, 20));
+    }
+}